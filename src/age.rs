@@ -0,0 +1,42 @@
+/// Coloring of cells by age, i.e. generations alive or generations since death. Fresh births
+/// and long lived still lifes get different colors, and recently dead cells leave fading trails.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeColoring {
+    /// Colors live cells go through as they age, from newborn to `max_age` generations old
+    pub gradient: Vec<[f32; 4]>,
+    /// Age at which live cells reach the last color of the gradient
+    pub max_age: u32,
+    /// Color of cells which just died. It fades to the dead color over `trail_length` generations
+    pub trail_color: [f32; 4],
+    /// Generations trails last, 0 for no trails
+    pub trail_length: u32,
+}
+
+impl Default for AgeColoring {
+    fn default() -> Self {
+        AgeColoring {
+            gradient: vec![
+                [1.0, 1.0, 0.6, 1.0],
+                [1.0, 0.5, 0.0, 1.0],
+                [0.8, 0.0, 0.0, 1.0],
+                [0.3, 0.0, 0.4, 1.0],
+            ],
+            max_age: 100,
+            trail_color: [0.1, 0.2, 0.5, 1.0],
+            trail_length: 16,
+        }
+    }
+}
+
+impl AgeColoring {
+    /// Colors as laid out in the compute shader: gradient followed by trail color
+    pub(crate) fn shader_colors(&self) -> Vec<[f32; 4]> {
+        assert!(
+            !self.gradient.is_empty(),
+            "Age gradient must have at least one color"
+        );
+        let mut colors = self.gradient.clone();
+        colors.push(self.trail_color);
+        colors
+    }
+}
@@ -0,0 +1,106 @@
+use bevy::math::IVec2;
+
+/// Determines what lies beyond the edges of the grid. Neighbours outside the grid are resolved
+/// back onto a grid cell (or to nothing) depending on the topology.
+/// Must match `resolve_pos` in the life compute shader.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BoundaryMode {
+    /// Cells outside the grid are always dead
+    Dead,
+    /// Edges wrap around to the opposite side
+    #[default]
+    Torus,
+    /// Edges reflect the grid like a mirror
+    Mirror,
+    /// Left and right edges wrap around, top and bottom wrap around flipped horizontally
+    KleinBottle,
+    /// All edges wrap around flipped
+    ProjectivePlane,
+}
+
+impl BoundaryMode {
+    /// Value of the mode in the compute shader
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            BoundaryMode::Dead => 0,
+            BoundaryMode::Torus => 1,
+            BoundaryMode::Mirror => 2,
+            BoundaryMode::KleinBottle => 3,
+            BoundaryMode::ProjectivePlane => 4,
+        }
+    }
+
+    /// Mode from its `as_u32` value
+    pub fn from_u32(value: u32) -> Option<BoundaryMode> {
+        match value {
+            0 => Some(BoundaryMode::Dead),
+            1 => Some(BoundaryMode::Torus),
+            2 => Some(BoundaryMode::Mirror),
+            3 => Some(BoundaryMode::KleinBottle),
+            4 => Some(BoundaryMode::ProjectivePlane),
+            _ => None,
+        }
+    }
+
+    /// Map a position, possibly outside of the grid, to the grid cell it refers to.
+    /// Returns `None` if the position lies outside and the boundary is dead.
+    pub fn resolve(&self, pos: IVec2, size: [u32; 2]) -> Option<IVec2> {
+        let (w, h) = (size[0] as i32, size[1] as i32);
+        if pos.x >= 0 && pos.x < w && pos.y >= 0 && pos.y < h {
+            return Some(pos);
+        }
+        // How many times we've wrapped over an edge. Odd crossings flip the other axis
+        let wraps_x = pos.x.div_euclid(w);
+        let wraps_y = pos.y.div_euclid(h);
+        let wrapped = IVec2::new(pos.x.rem_euclid(w), pos.y.rem_euclid(h));
+        match self {
+            BoundaryMode::Dead => None,
+            BoundaryMode::Torus => Some(wrapped),
+            BoundaryMode::Mirror => Some(IVec2::new(reflect(pos.x, w), reflect(pos.y, h))),
+            BoundaryMode::KleinBottle => {
+                let x = if wraps_y % 2 != 0 {
+                    w - 1 - wrapped.x
+                } else {
+                    wrapped.x
+                };
+                Some(IVec2::new(x, wrapped.y))
+            }
+            BoundaryMode::ProjectivePlane => {
+                let x = if wraps_y % 2 != 0 {
+                    w - 1 - wrapped.x
+                } else {
+                    wrapped.x
+                };
+                let y = if wraps_x % 2 != 0 {
+                    h - 1 - wrapped.y
+                } else {
+                    wrapped.y
+                };
+                Some(IVec2::new(x, y))
+            }
+        }
+    }
+}
+
+impl BoundaryMode {
+    /// Smallest shift per axis after which `resolve` repeats, `None` if the boundary is dead
+    pub(crate) fn period(&self, size: [u32; 2]) -> Option<IVec2> {
+        let size = IVec2::new(size[0] as i32, size[1] as i32);
+        match self {
+            BoundaryMode::Dead => None,
+            BoundaryMode::Torus => Some(size),
+            BoundaryMode::Mirror | BoundaryMode::ProjectivePlane => Some(size * 2),
+            BoundaryMode::KleinBottle => Some(IVec2::new(size.x, size.y * 2)),
+        }
+    }
+}
+
+/// Reflect coordinate into `0..len` so that `-1` maps to `0` and `len` maps to `len - 1`
+fn reflect(v: i32, len: i32) -> i32 {
+    let m = v.rem_euclid(2 * len);
+    if m >= len {
+        2 * len - 1 - m
+    } else {
+        m
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashSet;
+
+use bevy::math::IVec2;
+use rand::Rng;
+
+use crate::boundary::BoundaryMode;
+use crate::edit::{cell_random, EditCommand};
+use crate::simulation::{LifeRng, DEFAULT_DENSITY};
+
+/// Largest brush size. Brushes cover `O(size²)` cells per dab, some of which are rasterized on
+/// the CPU.
+pub const MAX_BRUSH_SIZE: u32 = 64;
+
+/// State written by toggling brushes. Resolved against the cell's state when it's written.
+pub(crate) const TOGGLE_STATE: u32 = u32::MAX;
+
+/// State of a cell after writing `state` over `current`, see `TOGGLE_STATE`
+pub(crate) fn edited_state(current: u32, state: u32) -> u32 {
+    match state {
+        // Dying states of Generations count as dead
+        TOGGLE_STATE => (current != 1) as u32,
+        _ => state,
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BrushShape {
+    #[default]
+    Circle,
+    Square,
+    /// Single cell regardless of size
+    Cell,
+    /// Straight line from where a stroke starts to where it ends, as thick as a circle of size
+    Line,
+}
+
+impl BrushShape {
+    /// Shapes in the order they're cycled through
+    pub const ALL: [BrushShape; 4] = [
+        BrushShape::Circle,
+        BrushShape::Square,
+        BrushShape::Cell,
+        BrushShape::Line,
+    ];
+
+    pub fn next(&self) -> BrushShape {
+        let i = BrushShape::ALL.iter().position(|s| s == self).unwrap();
+        BrushShape::ALL[(i + 1) % BrushShape::ALL.len()]
+    }
+}
+
+/// What a brush does to the cells it covers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BrushMode {
+    /// Make cells alive
+    Set,
+    /// Make cells dead, i.e. erase
+    Clear,
+    /// Make live cells dead and other cells alive
+    Toggle,
+    /// Make each cell alive with probability `density`, leaving the rest as they are
+    Random { density: f32 },
+}
+
+impl Default for BrushMode {
+    fn default() -> Self {
+        BrushMode::Random {
+            density: DEFAULT_DENSITY,
+        }
+    }
+}
+
+impl BrushMode {
+    /// Next mode. Random mode keeps its density.
+    pub fn next(&self) -> BrushMode {
+        match self {
+            BrushMode::Set => BrushMode::Clear,
+            BrushMode::Clear => BrushMode::Toggle,
+            BrushMode::Toggle => BrushMode::default(),
+            BrushMode::Random { .. } => BrushMode::Set,
+        }
+    }
+}
+
+/// Tool for editing the grid, used by `draw_life_system` & `GameOfLife::draw_brush`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub mode: BrushMode,
+    /// Radius in cells: half the side of a square and half the thickness of a line, not
+    /// counting the center cell. 0 covers a single cell. Resizing with keys stops at
+    /// `MAX_BRUSH_SIZE`.
+    pub size: u32,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Brush {
+            shape: BrushShape::default(),
+            mode: BrushMode::default(),
+            size: 6,
+        }
+    }
+}
+
+impl Brush {
+    /// Random circle, what `draw_life` paints
+    pub fn spray(radius: u32) -> Brush {
+        Brush {
+            shape: BrushShape::Circle,
+            mode: BrushMode::default(),
+            size: radius,
+        }
+    }
+
+    /// Cells covered by the brush moving from `from` to `to` and their new states, see
+    /// `edited_state`. Pass the same position for a single dab. Cells over the edges are
+    /// resolved by the boundary mode, and nothing is drawn if `to` is outside the grid. Draws
+    /// what `GameOfLife::draw_brush` draws with the same random numbers.
+    pub(crate) fn cells(
+        &self,
+        from: IVec2,
+        to: IVec2,
+        size: [u32; 2],
+        boundary_mode: BoundaryMode,
+        rng: &mut LifeRng,
+    ) -> Vec<(IVec2, u32)> {
+        let seed = rng.gen();
+        match self.edit_command(from, to, size, seed) {
+            Some(command) => command.rasterize(seed, size, boundary_mode),
+            None => vec![],
+        }
+    }
+
+    /// Brush moving from `from` to `to` as an edit command, with random numbers of `seed`, see
+    /// `cell_random`. `None` if `to` is outside the grid. Swept squares aren't a primitive of
+    /// edit commands, so they're rasterized here.
+    pub(crate) fn edit_command(
+        &self,
+        from: IVec2,
+        to: IVec2,
+        size: [u32; 2],
+        seed: u32,
+    ) -> Option<EditCommand> {
+        if to.y < 0 || to.y >= size[1] as i32 || to.x < 0 || to.x >= size[0] as i32 {
+            return None;
+        }
+        let mode = self.mode;
+        let radius = self.size;
+        Some(match self.shape {
+            BrushShape::Circle if from == to => EditCommand::Circle {
+                center: to,
+                radius,
+                mode,
+            },
+            BrushShape::Circle | BrushShape::Line => EditCommand::Line {
+                from,
+                to,
+                radius,
+                mode,
+            },
+            BrushShape::Cell => EditCommand::Line {
+                from,
+                to,
+                radius: 0,
+                mode,
+            },
+            BrushShape::Square if from == to => EditCommand::Rect {
+                min: to - IVec2::splat(radius as i32),
+                max: to + IVec2::splat(radius as i32),
+                mode,
+            },
+            BrushShape::Square => EditCommand::Cells(self.swept_square(from, to, seed)),
+        })
+    }
+
+    /// Cells of a square dabbed along the line from `from` to `to`, each once
+    fn swept_square(&self, from: IVec2, to: IVec2, seed: u32) -> Vec<(IVec2, u32)> {
+        let radius = self.size as i32;
+        let mut cells = vec![];
+        let mut covered = HashSet::new();
+        for center in line_cells(from, to) {
+            for y in -radius..=radius {
+                for x in -radius..=radius {
+                    let pos = center + IVec2::new(x, y);
+                    if !covered.insert(pos) {
+                        continue;
+                    }
+                    match self.mode {
+                        BrushMode::Set => cells.push((pos, 1)),
+                        BrushMode::Clear => cells.push((pos, 0)),
+                        BrushMode::Toggle => cells.push((pos, TOGGLE_STATE)),
+                        BrushMode::Random { density } => {
+                            if cell_random(seed, pos) < density {
+                                cells.push((pos, 1));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// Cells of a line from `from` to `to` inclusive, with Bresenham's algorithm
+pub(crate) fn line_cells(from: IVec2, to: IVec2) -> Vec<IVec2> {
+    let delta = (to - from).abs();
+    let step = (to - from).signum();
+    let mut error = delta.x - delta.y;
+    let mut pos = from;
+    let mut cells = vec![pos];
+    while pos != to {
+        let e2 = 2 * error;
+        if e2 > -delta.y {
+            error -= delta.y;
+            pos.x += step.x;
+        }
+        if e2 < delta.x {
+            error += delta.x;
+            pos.y += step.y;
+        }
+        cells.push(pos);
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_cells_are_connected() {
+        assert_eq!(
+            line_cells(IVec2::new(2, 3), IVec2::new(2, 3)),
+            vec![IVec2::new(2, 3)]
+        );
+        assert_eq!(
+            line_cells(IVec2::new(0, 0), IVec2::new(3, -3)),
+            (0..=3).map(|i| IVec2::new(i, -i)).collect::<Vec<IVec2>>()
+        );
+        let cells = line_cells(IVec2::new(5, 1), IVec2::new(-2, 4));
+        assert_eq!(cells.first(), Some(&IVec2::new(5, 1)));
+        assert_eq!(cells.last(), Some(&IVec2::new(-2, 4)));
+        assert_eq!(cells.len(), 8);
+        for pair in cells.windows(2) {
+            let step = (pair[1] - pair[0]).abs();
+            assert!(step.x <= 1 && step.y <= 1 && step != IVec2::ZERO);
+        }
+    }
+
+    #[test]
+    fn swept_square_covers_cells_once() {
+        let brush = Brush {
+            shape: BrushShape::Square,
+            mode: BrushMode::Toggle,
+            size: 1,
+        };
+        let dab = brush.swept_square(IVec2::ZERO, IVec2::ZERO, 0);
+        assert_eq!(dab.len(), 9);
+        assert!(dab.iter().all(|(_, state)| *state == TOGGLE_STATE));
+
+        // Two squares of 3x3 overlapping in a column
+        let cells = brush.swept_square(IVec2::ZERO, IVec2::new(2, 0), 0);
+        let positions = cells
+            .iter()
+            .map(|(pos, _)| *pos)
+            .collect::<HashSet<IVec2>>();
+        assert_eq!(positions.len(), cells.len());
+        assert_eq!(cells.len(), 15);
+        assert!(positions.contains(&IVec2::new(-1, -1)) && positions.contains(&IVec2::new(3, 1)));
+
+        let clear = Brush {
+            mode: BrushMode::Clear,
+            ..brush
+        };
+        let cells = clear.swept_square(IVec2::ZERO, IVec2::new(0, 1), 0);
+        assert_eq!(cells.len(), 12);
+        assert!(cells.iter().all(|(_, state)| *state == 0));
+    }
+
+    #[test]
+    fn toggle_resolves_against_current_state() {
+        assert_eq!(edited_state(0, TOGGLE_STATE), 1);
+        assert_eq!(edited_state(1, TOGGLE_STATE), 0);
+        // Dying cells count as dead
+        assert_eq!(edited_state(2, TOGGLE_STATE), 1);
+        for current in 0..3 {
+            assert_eq!(edited_state(current, 0), 0);
+            assert_eq!(edited_state(current, 1), 1);
+        }
+    }
+}
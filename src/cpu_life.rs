@@ -0,0 +1,303 @@
+use bevy::math::IVec2;
+use rand::SeedableRng;
+use rayon::prelude::*;
+
+use crate::boundary::BoundaryMode;
+use crate::brush::{edited_state, Brush};
+use crate::initializer::GridInitializer;
+use crate::rule::LifeRule;
+use crate::simulation::{random_seed, LifeRng, LifeSimulation, UnsupportedRule};
+
+/// CPU reference implementation of `GameOfLife`. Rows are computed in parallel with rayon.
+/// Useful for running the simulation headless, and for checking GPU results against.
+pub struct CpuGameOfLife {
+    size: [u32; 2],
+    grid: Vec<u32>,
+    // Next step is written here, then swapped with grid
+    next_grid: Vec<u32>,
+    rule: LifeRule,
+    boundary_mode: BoundaryMode,
+    seed: u64,
+    rng: LifeRng,
+}
+
+impl CpuGameOfLife {
+    /// Create with a random grid
+    pub fn new(size: [u32; 2]) -> CpuGameOfLife {
+        CpuGameOfLife::with_initializer(size, random_seed(), &GridInitializer::default())
+    }
+
+    /// Create with a random grid of `density` live cells, generated from seed. Same seed &
+    /// density give the same grid as `GameOfLife::with_seed`.
+    pub fn with_seed(size: [u32; 2], seed: u64, density: f32) -> CpuGameOfLife {
+        CpuGameOfLife::with_initializer(size, seed, &GridInitializer::Soup { density })
+    }
+
+    /// Create with a grid from the initializer, using seed for random initializers. Same seed &
+    /// initializer give the same grid as `GameOfLife::with_initializer`.
+    pub fn with_initializer(
+        size: [u32; 2],
+        seed: u64,
+        initializer: &GridInitializer,
+    ) -> CpuGameOfLife {
+        let mut rng = LifeRng::seed_from_u64(seed);
+        let cells = initializer.cells(size, &mut rng);
+        CpuGameOfLife {
+            rng,
+            seed,
+            ..CpuGameOfLife::from_grid(size, cells)
+        }
+    }
+
+    /// Create with given cell states, row by row. Brushes are seeded randomly, see `set_seed`.
+    pub fn from_grid(size: [u32; 2], cells: Vec<u32>) -> CpuGameOfLife {
+        assert_eq!(
+            cells.len(),
+            (size[0] * size[1]) as usize,
+            "Grid must have a state for each cell"
+        );
+        let seed = random_seed();
+        CpuGameOfLife {
+            size,
+            next_grid: vec![0; cells.len()],
+            grid: cells,
+            rule: LifeRule::default(),
+            boundary_mode: BoundaryMode::default(),
+            seed,
+            rng: LifeRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Replace the grid with one from the initializer. Random initializers continue the RNG of
+    /// brushes.
+    pub fn reset(&mut self, initializer: &GridInitializer) {
+        self.grid = initializer.cells(self.size, &mut self.rng);
+    }
+
+    /// Restart random numbers of brushes from seed
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = LifeRng::seed_from_u64(seed);
+    }
+
+    pub fn cell(&self, pos: IVec2) -> u32 {
+        self.grid[(pos.y * self.size[0] as i32 + pos.x) as usize]
+    }
+
+    pub fn set_cell(&mut self, pos: IVec2, state: u32) {
+        self.grid[(pos.y * self.size[0] as i32 + pos.x) as usize] = state;
+    }
+
+    fn step(&mut self) {
+        let size = self.size;
+        let rule = self.rule;
+        let boundary_mode = self.boundary_mode;
+        let grid = &self.grid;
+        let radius = rule.radius() as i32;
+        let neighbourhood = rule.neighbourhood();
+        self.next_grid
+            .par_chunks_mut(size[0] as usize)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, next) in row.iter_mut().enumerate() {
+                    let pos = IVec2::new(x as i32, y as i32);
+                    let mut alive_count = 0;
+                    for dy in -radius..=radius {
+                        for dx in -radius..=radius {
+                            if !neighbourhood.contains(dx, dy, rule.radius())
+                                || (dx == 0 && dy == 0 && !rule.include_center())
+                            {
+                                continue;
+                            }
+                            if let Some(n) = boundary_mode.resolve(pos + IVec2::new(dx, dy), size) {
+                                if grid[(n.y * size[0] as i32 + n.x) as usize] == 1 {
+                                    alive_count += 1;
+                                }
+                            }
+                        }
+                    }
+                    *next = rule.next_state(grid[y * size[0] as usize + x], alive_count);
+                }
+            });
+        std::mem::swap(&mut self.grid, &mut self.next_grid);
+    }
+}
+
+impl LifeSimulation for CpuGameOfLife {
+    fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    fn rule(&self) -> LifeRule {
+        self.rule
+    }
+
+    fn set_rule(&mut self, rule: LifeRule) -> Result<(), UnsupportedRule> {
+        self.rule = rule;
+        Ok(())
+    }
+
+    fn boundary_mode(&self) -> BoundaryMode {
+        self.boundary_mode
+    }
+
+    fn set_boundary_mode(&mut self, boundary_mode: BoundaryMode) {
+        self.boundary_mode = boundary_mode;
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn draw_brush(&mut self, brush: &Brush, from: IVec2, to: IVec2) {
+        let cells = brush.cells(from, to, self.size, self.boundary_mode, &mut self.rng);
+        for (cell, state) in cells {
+            self.set_cell(cell, edited_state(self.cell(cell), state));
+        }
+    }
+
+    fn compute_steps(&mut self, steps: u32) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+
+    fn grid(&mut self) -> Vec<u32> {
+        self.grid.clone()
+    }
+
+    fn set_grid(&mut self, cells: &[u32]) {
+        assert_eq!(
+            cells.len(),
+            self.grid.len(),
+            "Grid must have a state for each cell"
+        );
+        self.grid.copy_from_slice(cells);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_cells(size: [u32; 2], cells: &[(i32, i32)]) -> CpuGameOfLife {
+        let mut life = CpuGameOfLife::from_grid(size, vec![0; (size[0] * size[1]) as usize]);
+        for &(x, y) in cells {
+            life.set_cell(IVec2::new(x, y), 1);
+        }
+        life
+    }
+
+    fn live_cells(life: &CpuGameOfLife) -> Vec<(i32, i32)> {
+        let size = life.size();
+        let mut cells = vec![];
+        for y in 0..size[1] as i32 {
+            for x in 0..size[0] as i32 {
+                if life.cell(IVec2::new(x, y)) == 1 {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Live cells after one step of a pattern in the boundary mode, sorted by row
+    fn step_with(boundary_mode: BoundaryMode, cells: &[(i32, i32)]) -> Vec<(i32, i32)> {
+        let mut life = with_cells([6, 6], cells);
+        life.set_boundary_mode(boundary_mode);
+        life.compute();
+        live_cells(&life)
+    }
+
+    #[test]
+    fn blinker_has_period_two() {
+        let vertical = [(2, 1), (2, 2), (2, 3)];
+        let mut life = with_cells([5, 5], &vertical);
+        life.set_boundary_mode(BoundaryMode::Dead);
+        life.compute();
+        assert_eq!(live_cells(&life), vec![(1, 2), (2, 2), (3, 2)]);
+        life.compute();
+        assert_eq!(live_cells(&life), vertical.to_vec());
+    }
+
+    #[test]
+    fn glider_wraps_around_torus() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut life = with_cells([8, 8], &glider);
+        let start = life.grid();
+        // Glider moves a cell diagonally every 4 steps
+        life.compute_steps(4);
+        assert_ne!(life.grid(), start);
+        assert_eq!(live_cells(&life).len(), 5);
+        life.compute_steps(4 * 8 - 4);
+        assert_eq!(life.grid(), start);
+    }
+
+    #[test]
+    fn boundary_modes_at_edges() {
+        // Blinkers which turn over the left & bottom edges, centered off the middle so that
+        // flipped edges land elsewhere than wrapped ones
+        let left = [(0, 1), (0, 2), (0, 3)];
+        let bottom = [(1, 0), (2, 0), (3, 0)];
+
+        assert_eq!(step_with(BoundaryMode::Dead, &left), vec![(0, 2), (1, 2)]);
+        assert_eq!(step_with(BoundaryMode::Dead, &bottom), vec![(2, 0), (2, 1)]);
+
+        assert_eq!(
+            step_with(BoundaryMode::Torus, &left),
+            vec![(0, 2), (1, 2), (5, 2)]
+        );
+        assert_eq!(
+            step_with(BoundaryMode::Torus, &bottom),
+            vec![(2, 0), (2, 1), (2, 5)]
+        );
+
+        // Cells beyond the edge mirror the edge row, so the middle cell is overcrowded
+        assert_eq!(
+            step_with(BoundaryMode::Mirror, &bottom),
+            vec![(1, 0), (3, 0), (2, 1)]
+        );
+
+        // Klein bottle wraps left & right plainly, top & bottom flipped
+        assert_eq!(
+            step_with(BoundaryMode::KleinBottle, &left),
+            vec![(0, 2), (1, 2), (5, 2)]
+        );
+        assert_eq!(
+            step_with(BoundaryMode::KleinBottle, &bottom),
+            vec![(2, 0), (2, 1), (3, 5)]
+        );
+
+        // Projective plane flips both
+        assert_eq!(
+            step_with(BoundaryMode::ProjectivePlane, &left),
+            vec![(0, 2), (1, 2), (5, 3)]
+        );
+        assert_eq!(
+            step_with(BoundaryMode::ProjectivePlane, &bottom),
+            vec![(2, 0), (2, 1), (3, 5)]
+        );
+    }
+
+    #[test]
+    fn seeded_drawing_reproduces() {
+        let draw = |seed: u64| {
+            let mut life = CpuGameOfLife::with_initializer([32, 32], seed, &GridInitializer::Empty);
+            life.draw_life(IVec2::new(16, 16), 6);
+            life.draw_life(IVec2::new(0, 0), 4);
+            life.grid()
+        };
+        let grid = draw(7);
+        assert!(grid.contains(&1));
+        assert_eq!(draw(7), grid);
+        assert_ne!(draw(8), grid);
+    }
+
+    #[test]
+    fn seeded_soup_reproduces() {
+        let mut a = CpuGameOfLife::with_seed([16, 16], 3, 0.5);
+        let mut b =
+            CpuGameOfLife::with_initializer([16, 16], 3, &GridInitializer::Soup { density: 0.5 });
+        assert_eq!(a.grid(), b.grid());
+    }
+}
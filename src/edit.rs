@@ -0,0 +1,739 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+};
+
+use bevy::math::{IVec2, UVec2};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+};
+
+use crate::boundary::BoundaryMode;
+use crate::brush::{BrushMode, TOGGLE_STATE};
+use crate::undo::{CellChange, EditDiff};
+
+/// Edit of the grid, queued with `GameOfLife::edit` and applied on the GPU before the next
+/// step. Positions may lie over the edges, they're resolved by the boundary mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditCommand {
+    /// Cells within radius of center, like a circle brush
+    Circle {
+        center: IVec2,
+        radius: u32,
+        mode: BrushMode,
+    },
+    /// Cells from min to max, inclusive
+    Rect {
+        min: IVec2,
+        max: IVec2,
+        mode: BrushMode,
+    },
+    /// Cells within radius of the segment from `from` to `to`, i.e. a capsule
+    Line {
+        from: IVec2,
+        to: IVec2,
+        radius: u32,
+        mode: BrushMode,
+    },
+    /// Cells with their new states, e.g. a stamped pattern
+    Cells(Vec<(IVec2, u32)>),
+}
+
+impl EditCommand {
+    /// Area of capsules & rects from min to max, inclusive. None for cells.
+    fn bounds(&self) -> Option<(IVec2, IVec2)> {
+        match self {
+            EditCommand::Circle { center, radius, .. } => {
+                let r = IVec2::splat(*radius as i32);
+                Some((*center - r, *center + r))
+            }
+            EditCommand::Line {
+                from, to, radius, ..
+            } => {
+                let r = IVec2::splat(*radius as i32);
+                Some((from.min(*to) - r, from.max(*to) + r))
+            }
+            EditCommand::Rect { min, max, .. } => Some((min.min(*max), min.max(*max))),
+            EditCommand::Cells(_) => None,
+        }
+    }
+
+    /// Cells the edit covers with their new states, in the order the edit pass writes them.
+    /// Positions are resolved by the boundary mode, and cells covered more than once, e.g. by
+    /// a brush reaching over a wrapping edge, are written once: capsules & rects keep the first
+    /// write and cell edits the last. Random brushes draw with `cell_random` of the seed.
+    /// Capsules & rects are cut to the grid if the boundary is dead, and otherwise to two periods
+    /// of the boundary per axis from their min corner, so that large ones don't take time beyond
+    /// the grid size. Areas that fit in the cut are written exactly.
+    pub(crate) fn rasterize(
+        &self,
+        seed: u32,
+        size: [u32; 2],
+        boundary_mode: BoundaryMode,
+    ) -> Vec<(IVec2, u32)> {
+        let mut cells: Vec<(IVec2, u32)> = vec![];
+        // Index of each written cell in cells
+        let mut written = HashMap::new();
+        let mut write = |pos: IVec2, state: u32, overwrite: bool| {
+            let pos = match boundary_mode.resolve(pos, size) {
+                Some(pos) => pos,
+                None => return,
+            };
+            match written.entry(pos) {
+                Entry::Vacant(entry) => {
+                    entry.insert(cells.len());
+                    cells.push((pos, state));
+                }
+                Entry::Occupied(entry) if overwrite => cells[*entry.get()].1 = state,
+                Entry::Occupied(_) => {}
+            }
+        };
+        if let EditCommand::Cells(edit_cells) = self {
+            for (pos, state) in edit_cells.iter() {
+                write(*pos, *state, true);
+            }
+        } else {
+            let (min, max) = self.bounds().unwrap();
+            let (min, max) = match boundary_mode.period(size) {
+                Some(period) => {
+                    let end = IVec2::new(
+                        min.x.saturating_add(2 * period.x - 1),
+                        min.y.saturating_add(2 * period.y - 1),
+                    );
+                    (min, max.min(end))
+                }
+                None => {
+                    let end = IVec2::new(size[0] as i32 - 1, size[1] as i32 - 1);
+                    (min.max(IVec2::ZERO), max.min(end))
+                }
+            };
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let pos = IVec2::new(x, y);
+                    if let Some(state) = self.area_state(seed, pos) {
+                        write(pos, state, false);
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    /// State a capsule or rect writes to a cell in its bounds, None if the cell is left as it
+    /// is. Must match main of the edit shader.
+    fn area_state(&self, seed: u32, pos: IVec2) -> Option<u32> {
+        let mode = match self {
+            EditCommand::Circle {
+                center,
+                radius,
+                mode,
+            } => in_capsule(pos, *center, *center, *radius).then_some(mode)?,
+            EditCommand::Line {
+                from,
+                to,
+                radius,
+                mode,
+            } => in_capsule(pos, *from, *to, *radius).then_some(mode)?,
+            EditCommand::Rect { mode, .. } => mode,
+            EditCommand::Cells(_) => return None,
+        };
+        match mode {
+            BrushMode::Set => Some(1),
+            BrushMode::Clear => Some(0),
+            BrushMode::Toggle => Some(TOGGLE_STATE),
+            BrushMode::Random { density } => (cell_random(seed, pos) < *density).then_some(1),
+        }
+    }
+
+    /// Most cells a capsule can cover, `usize::MAX` for other edits. Grown by half a cell to
+    /// `ρ = radius + 0.5`, a capsule fits a rectangle of sides `length + 2ρ` & `2ρ`, and convex
+    /// shapes of area A & perimeter P cover at most `A + P / 2 + 1` cells.
+    fn max_covered(&self) -> usize {
+        let (from, to, radius) = match self {
+            EditCommand::Circle { center, radius, .. } => (*center, *center, *radius),
+            EditCommand::Line {
+                from, to, radius, ..
+            } => (*from, *to, *radius),
+            _ => return usize::MAX,
+        };
+        let side = 2 * radius as usize + 2;
+        ((to - from).as_vec2().length() + side as f32).ceil() as usize * side
+    }
+}
+
+/// Whether the capsule from `from` to `to` covers the cell. Matches the rounding of circle
+/// brushes. Must match in_capsule of the edit shader.
+fn in_capsule(pos: IVec2, from: IVec2, to: IVec2, radius: u32) -> bool {
+    let p = pos.as_vec2();
+    let a = from.as_vec2();
+    let ab = to.as_vec2() - a;
+    let len_sq = ab.dot(ab);
+    let t = if len_sq > 0.0 {
+        ((p - a).dot(ab) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (p - (a + t * ab)).length() < radius as f32 + 0.5
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+/// Random number from 0 to 1 for the cell, same for the same seed. Random brushes draw with it
+/// on the CPU & GPU alike, so it must match random of the edit shader.
+pub(crate) fn cell_random(seed: u32, pos: IVec2) -> f32 {
+    let h = hash(seed ^ hash(pos.x as u32 ^ hash(pos.y as u32)));
+    (h >> 8) as f32 / 16_777_216.0
+}
+
+/// Edit waiting for the edit pass
+#[derive(Debug, Clone)]
+pub(crate) struct QueuedEdit {
+    pub command: EditCommand,
+    /// Seed of the command's random numbers
+    pub seed: u32,
+    /// Undo group the edit's diff is recorded to, if it can be undone
+    pub undo_group: Option<u64>,
+}
+
+/// Cells changed by the undoable edits of one submission, appended by the edit pass: the number
+/// of changes, then three words per change: number of the edit, index of the cell and
+/// `old | new << 8`.
+pub(crate) struct EditReadback {
+    buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+    // Undo group of each undoable edit
+    groups: Vec<u64>,
+}
+
+impl EditReadback {
+    /// Undo groups of the undoable edits in order
+    pub fn groups(&self) -> &[u64] {
+        &self.groups
+    }
+
+    /// Diffs of the edits which changed cells in order, with their undo groups. None while the
+    /// GPU is still using the buffer.
+    pub fn diffs(&self) -> Option<Vec<(u64, EditDiff)>> {
+        let words = self.buffer.read().ok()?;
+        let len = (words[0] as usize).min((words.len() - 1) / 3);
+        // Edits run one after another, so changes of each edit follow each other
+        let mut diffs: Vec<(u32, EditDiff)> = vec![];
+        for entry in words[1..1 + len * 3].chunks_exact(3) {
+            let change = CellChange {
+                index: entry[1],
+                old: entry[2] as u8,
+                new: (entry[2] >> 8) as u8,
+            };
+            match diffs.last_mut() {
+                Some((edit, diff)) if *edit == entry[0] => diff.merge([change]),
+                _ => diffs.push((entry[0], EditDiff::from_changes([change]))),
+            }
+        }
+        let diffs = diffs
+            .into_iter()
+            .map(|(edit, diff)| (self.groups[edit as usize], diff))
+            .collect();
+        Some(diffs)
+    }
+}
+
+/// Compute pass applying queued edits to a grid buffer, one dispatch per edit
+pub(crate) struct EditPass {
+    compute_queue: Arc<Queue>,
+    pipeline: Arc<ComputePipeline>,
+}
+
+impl EditPass {
+    pub fn new(compute_queue: Arc<Queue>) -> EditPass {
+        let pipeline = {
+            let shader = compute_edit_cs::load(compute_queue.device().clone()).unwrap();
+            ComputePipeline::new(
+                compute_queue.device().clone(),
+                shader.entry_point("main").unwrap(),
+                &(),
+                None,
+                |_| {},
+            )
+            .unwrap()
+        };
+        EditPass {
+            compute_queue,
+            pipeline,
+        }
+    }
+
+    /// Record edits of the grid in order. Binds the edit pipeline, so other pipelines must be
+    /// bound again after. Returns the readback of cells changed by undoable edits, if any.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        grid: Arc<CpuAccessibleBuffer<[u32]>>,
+        ages: Arc<CpuAccessibleBuffer<[u32]>>,
+        edits: &[QueuedEdit],
+        size: [u32; 2],
+        packed: bool,
+        boundary_mode: BoundaryMode,
+    ) -> Option<EditReadback> {
+        if edits.is_empty() {
+            return None;
+        }
+        // Capsules & rects run over their area clipped to the grid. Reaching over edges which
+        // don't cut them off, cells could resolve to the same cell and be written twice, so
+        // they're rasterized here like cell edits.
+        let grid_max = IVec2::new(size[0] as i32 - 1, size[1] as i32 - 1);
+        let mut cells = vec![];
+        let mut dispatches = vec![];
+        for edit in edits.iter() {
+            let area = match edit.command.bounds() {
+                Some((min, max))
+                    if boundary_mode == BoundaryMode::Dead
+                        || (min.cmpge(IVec2::ZERO).all() && max.cmple(grid_max).all()) =>
+                {
+                    Some((min.max(IVec2::ZERO), max.min(grid_max)))
+                }
+                _ => None,
+            };
+            let dispatch = match area {
+                Some((min, max)) if max.cmplt(min).any() => continue,
+                Some((min, max)) => Dispatch::Area {
+                    origin: min,
+                    extent: (max - min + IVec2::ONE).as_uvec2(),
+                },
+                None => {
+                    let edit_cells = edit.command.rasterize(edit.seed, size, boundary_mode);
+                    if edit_cells.is_empty() {
+                        continue;
+                    }
+                    let offset = (cells.len() / 3) as u32;
+                    for (pos, state) in edit_cells.iter() {
+                        cells.extend_from_slice(&[pos.x, pos.y, *state as i32]);
+                    }
+                    Dispatch::Cells {
+                        offset,
+                        len: edit_cells.len() as u32,
+                    }
+                }
+            };
+            dispatches.push((edit, dispatch));
+        }
+        if dispatches.is_empty() {
+            return None;
+        }
+        // Buffer can't be empty
+        if cells.is_empty() {
+            cells.extend_from_slice(&[0; 3]);
+        }
+        let cells_buffer = CpuAccessibleBuffer::from_iter(
+            self.compute_queue.device().clone(),
+            BufferUsage::all(),
+            false,
+            cells,
+        )
+        .unwrap();
+
+        // Room for every change of undoable edits after the count of changes
+        let mut groups = vec![];
+        let mut undo_capacity = 0;
+        for (edit, dispatch) in dispatches.iter() {
+            if let Some(group) = edit.undo_group {
+                groups.push(group);
+                undo_capacity += dispatch.capacity(&edit.command);
+            }
+        }
+        let undo_buffer = CpuAccessibleBuffer::from_iter(
+            self.compute_queue.device().clone(),
+            BufferUsage::all(),
+            true,
+            (0..1 + undo_capacity * 3).map(|_| 0u32),
+        )
+        .unwrap();
+
+        let pipeline_layout = self.pipeline.layout();
+        let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
+        let set = PersistentDescriptorSet::new(
+            desc_layout.clone(),
+            [
+                WriteDescriptorSet::buffer(0, grid),
+                WriteDescriptorSet::buffer(1, cells_buffer),
+                WriteDescriptorSet::buffer(2, undo_buffer.clone()),
+                WriteDescriptorSet::buffer(3, ages),
+            ],
+        )
+        .unwrap();
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set);
+
+        let mut undo_edit = 0;
+        for (edit, dispatch) in dispatches.iter() {
+            let mut push_constants = compute_edit_cs::ty::PushConstants {
+                kind: EDIT_CAPSULE,
+                mode: 0,
+                origin: [0; 2],
+                extent: [0; 2],
+                from: [0; 2],
+                to: [0; 2],
+                radius: 0.0,
+                density: 0.0,
+                seed: edit.seed,
+                cells_offset: 0,
+                cells_len: 0,
+                undo_edit,
+                record: edit.undo_group.is_some() as u32,
+                width: size[0],
+                height: size[1],
+                packed: packed as u32,
+            };
+            if edit.undo_group.is_some() {
+                undo_edit += 1;
+            }
+            let (origin, extent) = match *dispatch {
+                Dispatch::Area { origin, extent } => (origin, extent),
+                Dispatch::Cells { offset, len } => {
+                    push_constants.kind = EDIT_CELLS;
+                    push_constants.cells_offset = offset;
+                    push_constants.cells_len = len;
+                    builder
+                        .push_constants(pipeline_layout.clone(), 0, push_constants)
+                        .dispatch([(len + 63) / 64, 1, 1])
+                        .unwrap();
+                    continue;
+                }
+            };
+            match &edit.command {
+                EditCommand::Circle {
+                    center,
+                    radius,
+                    mode,
+                } => {
+                    push_constants.from = center.to_array();
+                    push_constants.to = center.to_array();
+                    push_constants.radius = *radius as f32;
+                    set_mode(&mut push_constants, *mode);
+                }
+                EditCommand::Line {
+                    from,
+                    to,
+                    radius,
+                    mode,
+                } => {
+                    push_constants.from = from.to_array();
+                    push_constants.to = to.to_array();
+                    push_constants.radius = *radius as f32;
+                    set_mode(&mut push_constants, *mode);
+                }
+                EditCommand::Rect { mode, .. } => {
+                    push_constants.kind = EDIT_RECT;
+                    set_mode(&mut push_constants, *mode);
+                }
+                EditCommand::Cells(_) => unreachable!("Cell edits are rasterized"),
+            }
+            push_constants.origin = origin.to_array();
+            push_constants.extent = extent.to_array();
+            builder
+                .push_constants(pipeline_layout.clone(), 0, push_constants)
+                .dispatch([(extent.x + 7) / 8, (extent.y + 7) / 8, 1])
+                .unwrap();
+        }
+        (!groups.is_empty()).then(|| EditReadback {
+            buffer: undo_buffer,
+            groups,
+        })
+    }
+}
+
+/// Cells an edit is dispatched over
+enum Dispatch {
+    /// Area of a capsule or rect within the grid
+    Area { origin: IVec2, extent: UVec2 },
+    /// Resolved cells in the cells buffer
+    Cells { offset: u32, len: u32 },
+}
+
+impl Dispatch {
+    /// Most cells the edit can change
+    fn capacity(&self, command: &EditCommand) -> usize {
+        match *self {
+            Dispatch::Area { extent, .. } => {
+                command.max_covered().min((extent.x * extent.y) as usize)
+            }
+            Dispatch::Cells { len, .. } => len as usize,
+        }
+    }
+}
+
+const EDIT_CAPSULE: u32 = 0;
+const EDIT_RECT: u32 = 1;
+const EDIT_CELLS: u32 = 2;
+
+fn set_mode(push_constants: &mut compute_edit_cs::ty::PushConstants, mode: BrushMode) {
+    push_constants.mode = match mode {
+        BrushMode::Set => 0,
+        BrushMode::Clear => 1,
+        BrushMode::Toggle => 2,
+        BrushMode::Random { density } => {
+            push_constants.density = density;
+            3
+        }
+    };
+}
+
+mod compute_edit_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+// Grid of the current step, unpacked or packed, see GridStorage
+layout(set = 0, binding = 0) buffer GridBuffer { uint grid[]; };
+// x, y & state of each cell of rasterized edits, resolved to the grid and once per edit
+layout(set = 0, binding = 1) readonly buffer CellsBuffer { int cells[]; };
+// Count of changes by undoable edits, then edit, cell index & old | new << 8 of each change
+layout(set = 0, binding = 2) buffer UndoBuffer {
+    uint undo_len;
+    uint undo[];
+};
+// Ages of cells, unpacked grids only, see AgeBuffer of the life shaders
+layout(set = 0, binding = 3) buffer AgeBuffer { uint ages[]; };
+
+layout(push_constant) uniform PushConstants {
+    // Capsule, rect or cells
+    uint kind;
+    // See BrushMode
+    uint mode;
+    // Area covered by capsules & rects, within the grid
+    ivec2 origin;
+    uvec2 extent;
+    // Capsule segment
+    ivec2 from;
+    ivec2 to;
+    float radius;
+    float density;
+    uint seed;
+    // Cells of a cell edit in the cells buffer
+    uint cells_offset;
+    uint cells_len;
+    // Number of the edit among undoable ones, if it's recorded
+    uint undo_edit;
+    bool record;
+    // Grid size in cells
+    uint width;
+    uint height;
+    bool packed;
+} push_constants;
+
+#define EDIT_CAPSULE 0
+#define EDIT_RECT 1
+#define EDIT_CELLS 2
+
+#define MODE_SET 0
+#define MODE_CLEAR 1
+#define MODE_TOGGLE 2
+#define MODE_RANDOM 3
+
+// Must match TOGGLE_STATE in brush.rs
+#define TOGGLE_STATE 0xffffffffu
+
+#define AGE_ALIVE_BIT 0x80000000u
+
+uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+// Must match cell_random in edit.rs
+float random(ivec2 pos) {
+    uint h = hash(push_constants.seed ^ hash(uint(pos.x) ^ hash(uint(pos.y))));
+    return float(h >> 8) / 16777216.0;
+}
+
+// Whether a capsule covers the cell, must match in_capsule in edit.rs
+bool in_capsule(ivec2 pos) {
+    vec2 p = vec2(pos);
+    vec2 a = vec2(push_constants.from);
+    vec2 ab = vec2(push_constants.to) - a;
+    float len_sq = dot(ab, ab);
+    float t = len_sq > 0.0 ? clamp(dot(p - a, ab) / len_sq, 0.0, 1.0) : 0.0;
+    return length(p - (a + t * ab)) < push_constants.radius + 0.5;
+}
+
+// Write the state of the cell, appending the change to the undo buffer
+void write_state(ivec2 pos, uint state) {
+    uint old_state;
+    uint new_state;
+    if (push_constants.packed) {
+        uint words_per_row = (push_constants.width + 31) / 32;
+        uint index = uint(pos.y) * words_per_row + uint(pos.x) / 32;
+        uint bit = 1u << (uint(pos.x) % 32);
+        uint word;
+        if (state == TOGGLE_STATE) {
+            word = atomicXor(grid[index], bit);
+        } else if (state == 1) {
+            word = atomicOr(grid[index], bit);
+        } else {
+            word = atomicAnd(grid[index], ~bit);
+        }
+        old_state = (word & bit) != 0 ? 1 : 0;
+        new_state = state == TOGGLE_STATE ? 1 - old_state : (state == 1 ? 1 : 0);
+    } else {
+        uint index = uint(pos.y) * push_constants.width + uint(pos.x);
+        old_state = grid[index];
+        // Dying states of Generations toggle to alive
+        new_state = state == TOGGLE_STATE ? (old_state == 1 ? 0 : 1) : state;
+        grid[index] = new_state;
+        // Drawn cells are born or die like stepped ones
+        if (old_state != new_state) {
+            ages[index] = new_state == 1 ? AGE_ALIVE_BIT : 0u;
+        }
+    }
+    if (push_constants.record && old_state != new_state) {
+        uint u = atomicAdd(undo_len, 1u) * 3;
+        // Room for all changes is reserved, so this only guards against overflows
+        if (u + 2 < uint(undo.length())) {
+            undo[u] = push_constants.undo_edit;
+            undo[u + 1] = uint(pos.y) * push_constants.width + uint(pos.x);
+            undo[u + 2] = old_state | (new_state << 8);
+        }
+    }
+}
+
+void main() {
+    ivec2 pos;
+    uint state;
+    if (push_constants.kind == EDIT_CELLS) {
+        uint i = gl_WorkGroupID.x * 64 + gl_LocalInvocationIndex;
+        if (i >= push_constants.cells_len) {
+            return;
+        }
+        uint c = (push_constants.cells_offset + i) * 3;
+        pos = ivec2(cells[c], cells[c + 1]);
+        state = uint(cells[c + 2]);
+    } else {
+        if (any(greaterThanEqual(gl_GlobalInvocationID.xy, push_constants.extent))) {
+            return;
+        }
+        pos = push_constants.origin + ivec2(gl_GlobalInvocationID.xy);
+        if (push_constants.kind == EDIT_CAPSULE && !in_capsule(pos)) {
+            return;
+        }
+        if (push_constants.mode == MODE_SET) {
+            state = 1;
+        } else if (push_constants.mode == MODE_CLEAR) {
+            state = 0;
+        } else if (push_constants.mode == MODE_TOGGLE) {
+            state = TOGGLE_STATE;
+        } else if (random(pos) < push_constants.density) {
+            state = 1;
+        } else {
+            return;
+        }
+    }
+    write_state(pos, state);
+}"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn rasterize_writes_cells_once() {
+        // Wider than the grid, so that it wraps onto itself
+        let toggle = EditCommand::Circle {
+            center: IVec2::new(2, 2),
+            radius: 4,
+            mode: BrushMode::Toggle,
+        };
+        let cells = toggle.rasterize(0, [5, 5], BoundaryMode::Torus);
+        let positions = cells.iter().map(|(pos, _)| *pos).collect::<HashSet<_>>();
+        assert_eq!(positions.len(), cells.len());
+        assert_eq!(cells.len(), 25);
+
+        let dead = toggle.rasterize(0, [5, 5], BoundaryMode::Dead);
+        assert_eq!(dead.len(), 25);
+    }
+
+    #[test]
+    fn rasterize_cuts_large_areas() {
+        let rect = EditCommand::Rect {
+            min: IVec2::splat(-1_000_000),
+            max: IVec2::splat(1_000_000),
+            mode: BrushMode::Set,
+        };
+        for mode in [
+            BoundaryMode::Dead,
+            BoundaryMode::Torus,
+            BoundaryMode::Mirror,
+        ] {
+            let cells = rect.rasterize(0, [4, 3], mode);
+            assert_eq!(cells.len(), 12);
+            assert!(cells.iter().all(|(_, state)| *state == 1));
+        }
+
+        // Random rects are cut to two periods
+        let random = EditCommand::Rect {
+            min: IVec2::new(-7, -5),
+            max: IVec2::new(1_000_000, 1_000_000),
+            mode: BrushMode::Random { density: 0.5 },
+        };
+        let cut = EditCommand::Rect {
+            min: IVec2::new(-7, -5),
+            max: IVec2::new(0, 0),
+            mode: BrushMode::Random { density: 0.5 },
+        };
+        let mut cells = random.rasterize(3, [4, 3], BoundaryMode::Torus);
+        let mut cut_cells = cut.rasterize(3, [4, 3], BoundaryMode::Torus);
+        cells.sort_by_key(|(pos, _)| (pos.y, pos.x));
+        cut_cells.sort_by_key(|(pos, _)| (pos.y, pos.x));
+        assert_eq!(cells, cut_cells);
+    }
+
+    #[test]
+    fn rasterize_cells_keep_last_write() {
+        let cells = EditCommand::Cells(vec![
+            (IVec2::new(0, 0), 1),
+            (IVec2::new(4, 0), 0),
+            (IVec2::new(-1, 0), 2),
+        ]);
+        assert_eq!(
+            cells.rasterize(0, [4, 4], BoundaryMode::Torus),
+            vec![(IVec2::new(0, 0), 0), (IVec2::new(3, 0), 2)]
+        );
+        assert_eq!(
+            cells.rasterize(0, [4, 4], BoundaryMode::Dead),
+            vec![(IVec2::new(0, 0), 1)]
+        );
+    }
+
+    #[test]
+    fn random_cells_depend_on_seed() {
+        let spray = EditCommand::Rect {
+            min: IVec2::ZERO,
+            max: IVec2::splat(15),
+            mode: BrushMode::Random { density: 0.5 },
+        };
+        let cells = spray.rasterize(1, [16, 16], BoundaryMode::Dead);
+        assert_eq!(cells, spray.rasterize(1, [16, 16], BoundaryMode::Dead));
+        assert_ne!(cells, spray.rasterize(2, [16, 16], BoundaryMode::Dead));
+        assert!(cells.len() > 64 && cells.len() < 192);
+    }
+}
@@ -7,23 +7,205 @@
 // notice may not be copied, modified, or distributed except
 // according to those terms.
 
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
 
+use crate::age::AgeColoring;
+use crate::boundary::BoundaryMode;
+use crate::brush::{Brush, BrushMode};
+use crate::edit::{EditCommand, EditPass, EditReadback, QueuedEdit};
+use crate::initializer::GridInitializer;
+use crate::palette::{lut_texture, ColorMode, LifePalette, LutInput, PaletteTheme, ThemeColors};
+use crate::pattern::Pattern;
+use crate::rule::LifeRule;
+use crate::save::{SaveError, SaveReader, SaveWriter};
+use crate::simulation::{random_seed, LifeRng, LifeSimulation, UnsupportedRule};
+use crate::stats::GenerationStats;
+use crate::undo::{EditHistory, HistoryCommand};
 use bevy::math::IVec2;
-use rand::Rng;
-use vulkano::command_buffer::PrimaryCommandBuffer;
+use rand::{Rng, SeedableRng};
 use vulkano::{
     buffer::{BufferUsage, CpuAccessibleBuffer},
-    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfo, PrimaryAutoCommandBuffer,
+    },
     descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
     device::Queue,
     format::Format,
-    image::{ImageAccess, ImageUsage, StorageImage},
+    image::{ImageAccess, ImageUsage, ImageViewAbstract, StorageImage},
     pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
-    sync::GpuFuture,
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::{self, FenceSignalFuture, GpuFuture},
 };
 use vulkano_util::renderer::DeviceImageView;
 
+/// Cells per word in packed storage
+const CELLS_PER_WORD: u32 = 32;
+/// Largest color image dimension for packed grids, larger grids are downsampled
+const MAX_PACKED_IMAGE_SIZE: u32 = 2048;
+/// Bit of a cell's age set while it's alive, the rest count generations. Must match the shaders
+const AGE_ALIVE_BIT: u32 = 0x8000_0000;
+const AGE_MAX: u32 = 0x7fff_ffff;
+
+/// How cells are stored in the grid buffers
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GridStorage {
+    /// One `u32` per cell. Supports all rules
+    #[default]
+    Unpacked,
+    /// One bit per cell, 32 cells per word along rows. Rows are padded to whole words.
+    /// Supports two state B/S rules only, but needs 32x less memory & bandwidth, allowing
+    /// grids of 16k x 16k. Color image is downsampled for large grids,
+    /// see `GameOfLife::color_image`
+    Packed,
+}
+
+impl GridStorage {
+    /// Whether the storage can simulate the rule
+    pub fn supports(&self, rule: &LifeRule) -> bool {
+        match self {
+            GridStorage::Unpacked => true,
+            GridStorage::Packed => rule.states() == 2 && !rule.is_larger_than_life(),
+        }
+    }
+
+    /// Value of the storage in saves
+    fn as_u32(&self) -> u32 {
+        match self {
+            GridStorage::Unpacked => 0,
+            GridStorage::Packed => 1,
+        }
+    }
+
+    fn from_u32(value: u32) -> Option<GridStorage> {
+        match value {
+            0 => Some(GridStorage::Unpacked),
+            1 => Some(GridStorage::Packed),
+            _ => None,
+        }
+    }
+
+    /// Grid buffer of the initializer's cells, packed a row at a time
+    fn pack_initializer(
+        &self,
+        initializer: &GridInitializer,
+        size: [u32; 2],
+        rng: &mut LifeRng,
+    ) -> Vec<u32> {
+        let mut grid = Vec::with_capacity(self.buffer_len(size) as usize);
+        initializer.for_each_row(size, rng, |row| self.push_row(&mut grid, row));
+        grid
+    }
+
+    /// Append a row of cell states to a grid buffer
+    fn push_row(&self, grid: &mut Vec<u32>, row: &[u32]) {
+        match self {
+            GridStorage::Unpacked => grid.extend_from_slice(row),
+            GridStorage::Packed => grid.extend(row.chunks(CELLS_PER_WORD as usize).map(|cells| {
+                cells.iter().enumerate().fold(0, |word, (bit, state)| {
+                    word | (((*state == 1) as u32) << bit)
+                })
+            })),
+        }
+    }
+
+    /// Number of `u32`s in a grid buffer
+    fn buffer_len(&self, size: [u32; 2]) -> u32 {
+        match self {
+            GridStorage::Unpacked => size[0] * size[1],
+            GridStorage::Packed => words_per_row(size[0]) * size[1],
+        }
+    }
+
+    /// `buffer_len`, or None if the number of cells doesn't fit in a `u32`
+    fn checked_buffer_len(&self, size: [u32; 2]) -> Option<u32> {
+        size[0].checked_mul(size[1])?;
+        Some(self.buffer_len(size))
+    }
+
+    /// Index of the word holding the cell, and bit of the cell in the word (always 0 if unpacked)
+    fn cell_location(&self, pos: IVec2, size: [u32; 2]) -> (usize, u32) {
+        match self {
+            GridStorage::Unpacked => ((pos.y * size[0] as i32 + pos.x) as usize, 0),
+            GridStorage::Packed => (
+                (pos.y as u32 * words_per_row(size[0]) + pos.x as u32 / CELLS_PER_WORD) as usize,
+                pos.x as u32 % CELLS_PER_WORD,
+            ),
+        }
+    }
+
+    /// Read cell state from the grid
+    fn read_cell(&self, grid: &[u32], pos: IVec2, size: [u32; 2]) -> u32 {
+        let (index, bit) = self.cell_location(pos, size);
+        match self {
+            GridStorage::Unpacked => grid[index],
+            GridStorage::Packed => (grid[index] >> bit) & 1,
+        }
+    }
+
+    /// Write cell state to the grid. Packed grids only store whether the cell is alive
+    fn write_cell(&self, grid: &mut [u32], pos: IVec2, size: [u32; 2], state: u32) {
+        let (index, bit) = self.cell_location(pos, size);
+        match self {
+            GridStorage::Unpacked => grid[index] = state,
+            GridStorage::Packed if state == 1 => grid[index] |= 1 << bit,
+            GridStorage::Packed => grid[index] &= !(1 << bit),
+        }
+    }
+}
+
+/// Point of the grid which stays in place when it's resized, see `GameOfLife::resize`.
+/// Bottom is row 0.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ResizeAnchor {
+    #[default]
+    Center,
+    BottomLeft,
+    BottomRight,
+    TopLeft,
+    TopRight,
+}
+
+impl ResizeAnchor {
+    /// Where cells of the old grid land in the new grid
+    pub(crate) fn offset(&self, old_size: [u32; 2], new_size: [u32; 2]) -> IVec2 {
+        let diff = IVec2::new(
+            new_size[0] as i32 - old_size[0] as i32,
+            new_size[1] as i32 - old_size[1] as i32,
+        );
+        match self {
+            ResizeAnchor::Center => diff / 2,
+            ResizeAnchor::BottomLeft => IVec2::ZERO,
+            ResizeAnchor::BottomRight => IVec2::new(diff.x, 0),
+            ResizeAnchor::TopLeft => IVec2::new(0, diff.y),
+            ResizeAnchor::TopRight => diff,
+        }
+    }
+}
+
+fn words_per_row(width: u32) -> u32 {
+    // Doesn't overflow for any width
+    width / CELLS_PER_WORD + (width % CELLS_PER_WORD != 0) as u32
+}
+
+/// How many cells (per axis) each color image pixel covers. Power of two so that a pixel never
+/// covers cells from two words.
+fn packed_image_scale(size: [u32; 2]) -> u32 {
+    let max_dim = size[0].max(size[1]);
+    ((max_dim + MAX_PACKED_IMAGE_SIZE - 1) / MAX_PACKED_IMAGE_SIZE)
+        .next_power_of_two()
+        .min(CELLS_PER_WORD)
+}
+
+/// Edit or undo step waiting for the next submission
+enum PendingEdit {
+    Edit(QueuedEdit),
+    /// Resolved to an edit when it's recorded, see `GameOfLife::undo`
+    History(HistoryCommand),
+}
+
 /// Pipeline holding double buffered grid & color image.
 /// Grids are used to calculate the state, and color image is used to show the output.
 /// Because each step we determine state in parallel, we need to write the output to
@@ -35,28 +217,268 @@ pub struct GameOfLife {
     life_in: Arc<CpuAccessibleBuffer<[u32]>>,
     life_out: Arc<CpuAccessibleBuffer<[u32]>>,
     image: DeviceImageView,
+    size: [u32; 2],
+    storage: GridStorage,
     sim_steps: u32,
+    // Edits applied on the GPU before the next step, in order
+    pending_edits: VecDeque<PendingEdit>,
+    // Words replacing the current step before pending edits, see `set_grid`
+    pending_grid: Option<Arc<CpuAccessibleBuffer<[u32]>>>,
+    edit_pass: EditPass,
+    history: EditHistory,
+    // Diffs of submitted undoable edits, oldest first
+    edit_readbacks: Vec<EditReadback>,
+    rule: LifeRule,
+    boundary_mode: BoundaryMode,
+    seed: u64,
+    // Generates the initial grid, then brushes
+    rng: LifeRng,
+    state_colors: Vec<[f32; 4]>,
+    state_color_buffer: Arc<CpuAccessibleBuffer<[[f32; 4]]>>,
+    // Generations each cell has been alive or dead, unpacked storage only
+    ages: Arc<CpuAccessibleBuffer<[u32]>>,
+    age_coloring: AgeColoring,
+    age_color_buffer: Arc<CpuAccessibleBuffer<[[f32; 4]]>>,
+    color_mode: ColorMode,
+    // Dead, birth & survivor colors followed by theme gradient
+    palette_color_buffer: Arc<CpuAccessibleBuffer<[[f32; 4]]>>,
+    // Theme last applied by `set_palette`
+    theme: Option<PaletteTheme>,
+    lut: Arc<dyn ImageViewAbstract>,
+    // LUTs of theme gradients, uploaded once per theme
+    theme_luts: HashMap<PaletteTheme, Arc<dyn ImageViewAbstract>>,
+    lut_sampler: Arc<Sampler>,
+    compute_stats_pipeline: Arc<ComputePipeline>,
+    stats_enabled: bool,
+    // Stats of submitted steps, oldest first
+    stats_readbacks: Vec<StatsReadback>,
+    // Submissions which may not have finished, waited for before buffers are used on the CPU
+    submissions: Vec<Arc<FenceSignalFuture<Box<dyn GpuFuture>>>>,
+}
+
+/// Counts of steps computed in one submission, 3 per step: alive, births & deaths
+struct StatsReadback {
+    buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+    first_generation: u32,
+}
+
+/// Invocations per workgroup of the stats reduction
+const STATS_LOCAL_SIZE: u32 = 256;
+
+/// Stats readbacks kept until `take_stats`, oldest are dropped if it isn't called. Same as the
+/// history of `LifeStats`.
+const MAX_STATS_READBACKS: usize = 1024;
+
+/// Copy of a step's grid in a staging buffer, made by `GameOfLife::read_grid_after`
+pub struct GridReadback {
+    buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+    size: [u32; 2],
+    storage: GridStorage,
+}
+
+impl GridReadback {
+    /// Grid size in cells
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// One state per cell, row by row. `None` if the GPU hasn't finished the copy yet.
+    pub fn cells(&self) -> Option<Vec<u8>> {
+        let grid = self.grid()?;
+        Some(
+            (0..self.size[1] as i32)
+                .flat_map(|y| (0..self.size[0] as i32).map(move |x| IVec2::new(x, y)))
+                .map(|pos| self.storage.read_cell(&grid, pos, self.size) as u8)
+                .collect(),
+        )
+    }
+
+    /// Live cells as bits, 32 cells per word along rows. Rows are padded to whole words, like
+    /// `GridStorage::Packed`. `None` if the GPU hasn't finished the copy yet.
+    pub fn bitset(&self) -> Option<Vec<u32>> {
+        let grid = self.grid()?;
+        let packed = GridStorage::Packed;
+        let mut bits = vec![0; packed.buffer_len(self.size) as usize];
+        for y in 0..self.size[1] as i32 {
+            for x in 0..self.size[0] as i32 {
+                let pos = IVec2::new(x, y);
+                let state = self.storage.read_cell(&grid, pos, self.size);
+                packed.write_cell(&mut bits, pos, self.size, (state == 1) as u32);
+            }
+        }
+        Some(bits)
+    }
+
+    /// Grid words, if the copy has finished
+    fn grid(&self) -> Option<Vec<u32>> {
+        Some(self.buffer.read().ok()?.to_vec())
+    }
+}
+
+const DEFAULT_LIFE_COLOR: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const DEFAULT_DEAD_COLOR: [f32; 4] = [0.0; 4];
+
+fn grid_buffer(compute_queue: &Arc<Queue>, words: Vec<u32>) -> Arc<CpuAccessibleBuffer<[u32]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        false,
+        words,
+    )
+    .unwrap()
+}
+
+/// Color image of a grid. Packed grids larger than `MAX_PACKED_IMAGE_SIZE` get a smaller image
+fn color_image(
+    compute_queue: &Arc<Queue>,
+    size: [u32; 2],
+    storage: GridStorage,
+) -> DeviceImageView {
+    let image_size = match storage {
+        GridStorage::Unpacked => size,
+        GridStorage::Packed => {
+            let scale = packed_image_scale(size);
+            [(size[0] + scale - 1) / scale, (size[1] + scale - 1) / scale]
+        }
+    };
+    StorageImage::general_purpose_image_view(
+        compute_queue.clone(),
+        image_size,
+        Format::R8G8B8A8_UNORM,
+        ImageUsage {
+            sampled: true,
+            storage: true,
+            transfer_src: true,
+            transfer_dst: true,
+            ..ImageUsage::none()
+        },
+    )
+    .unwrap()
+}
+
+/// Ages of a new grid. Live cells are newborn, and the rest long dead so that they don't leave
+/// trails. Packed grids don't track ages, but the buffer can't be empty
+fn grid_ages(grid: &[u32], storage: GridStorage) -> Vec<u32> {
+    match storage {
+        GridStorage::Unpacked => grid.iter().map(|state| initial_age(*state)).collect(),
+        GridStorage::Packed => vec![0],
+    }
+}
+
+fn initial_age(state: u32) -> u32 {
+    if state == 1 {
+        AGE_ALIVE_BIT
+    } else {
+        AGE_MAX
+    }
+}
+
+fn age_buffer(compute_queue: &Arc<Queue>, ages: Vec<u32>) -> Arc<CpuAccessibleBuffer<[u32]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        false,
+        ages,
+    )
+    .unwrap()
 }
 
-fn rand_grid(compute_queue: &Arc<Queue>, size: [u32; 2]) -> Arc<CpuAccessibleBuffer<[u32]>> {
+fn color_buffer(
+    compute_queue: &Arc<Queue>,
+    colors: &[[f32; 4]],
+) -> Arc<CpuAccessibleBuffer<[[f32; 4]]>> {
     CpuAccessibleBuffer::from_iter(
         compute_queue.device().clone(),
         BufferUsage::all(),
         false,
-        (0..(size[0] * size[1]))
-            .map(|_| rand::thread_rng().gen_range(0u32..=1))
-            .collect::<Vec<u32>>(),
+        colors.iter().copied(),
     )
     .unwrap()
 }
 
+/// Colors as laid out in the compute shader: dead, birth & survivor colors followed by gradient
+fn palette_colors(theme: &ThemeColors) -> Vec<[f32; 4]> {
+    let mut colors = vec![theme.dead, theme.birth, theme.survivor];
+    colors.extend_from_slice(&theme.gradient);
+    colors
+}
+
+/// Colors for each state of the rule. Dying states fade from life color to dead color
+fn state_gradient(life_color: [f32; 4], dead_color: [f32; 4], states: u32) -> Vec<[f32; 4]> {
+    (0..states)
+        .map(|state| match state {
+            0 => dead_color,
+            1 => life_color,
+            _ => {
+                let t = (state - 1) as f32 / (states - 1) as f32;
+                let mut color = [0.0; 4];
+                for (i, c) in color.iter_mut().enumerate() {
+                    *c = life_color[i] + (dead_color[i] - life_color[i]) * t;
+                }
+                color
+            }
+        })
+        .collect()
+}
+
 impl GameOfLife {
     pub fn new(compute_queue: Arc<Queue>, size: [u32; 2]) -> GameOfLife {
-        let life_in = rand_grid(&compute_queue, size);
-        let life_out = rand_grid(&compute_queue, size);
+        GameOfLife::with_storage(compute_queue, size, GridStorage::default())
+    }
+
+    pub fn with_storage(
+        compute_queue: Arc<Queue>,
+        size: [u32; 2],
+        storage: GridStorage,
+    ) -> GameOfLife {
+        GameOfLife::with_initializer(
+            compute_queue,
+            size,
+            storage,
+            random_seed(),
+            &GridInitializer::default(),
+        )
+    }
+
+    /// Create with a random grid of `density` live cells, generated from seed. Brushes continue
+    /// from the same seed, so a run is reproducible from its seed and inputs.
+    pub fn with_seed(
+        compute_queue: Arc<Queue>,
+        size: [u32; 2],
+        storage: GridStorage,
+        seed: u64,
+        density: f32,
+    ) -> GameOfLife {
+        GameOfLife::with_initializer(
+            compute_queue,
+            size,
+            storage,
+            seed,
+            &GridInitializer::Soup { density },
+        )
+    }
+
+    /// Create with a grid from the initializer, using seed for random initializers. Brushes
+    /// continue from the same seed.
+    pub fn with_initializer(
+        compute_queue: Arc<Queue>,
+        size: [u32; 2],
+        storage: GridStorage,
+        seed: u64,
+        initializer: &GridInitializer,
+    ) -> GameOfLife {
+        let mut rng = LifeRng::seed_from_u64(seed);
+        let grid = storage.pack_initializer(initializer, size, &mut rng);
+        let ages = age_buffer(&compute_queue, grid_ages(&grid, storage));
+        let life_in = grid_buffer(&compute_queue, grid.clone());
+        let life_out = grid_buffer(&compute_queue, grid);
 
         let compute_life_pipeline = {
-            let shader = compute_life_cs::load(compute_queue.device().clone()).unwrap();
+            let shader = match storage {
+                GridStorage::Unpacked => compute_life_cs::load(compute_queue.device().clone()),
+                GridStorage::Packed => compute_packed_life_cs::load(compute_queue.device().clone()),
+            }
+            .unwrap();
             ComputePipeline::new(
                 compute_queue.device().clone(),
                 shader.entry_point("main").unwrap(),
@@ -67,15 +489,35 @@ impl GameOfLife {
             .unwrap()
         };
 
-        let image = StorageImage::general_purpose_image_view(
-            compute_queue.clone(),
-            size,
-            Format::R8G8B8A8_UNORM,
-            ImageUsage {
-                sampled: true,
-                storage: true,
-                transfer_dst: true,
-                ..ImageUsage::none()
+        let compute_stats_pipeline = {
+            let shader = compute_stats_cs::load(compute_queue.device().clone()).unwrap();
+            ComputePipeline::new(
+                compute_queue.device().clone(),
+                shader.entry_point("main").unwrap(),
+                &(),
+                None,
+                |_| {},
+            )
+            .unwrap()
+        };
+
+        let image = color_image(&compute_queue, size, storage);
+        let rule = LifeRule::default();
+        let state_colors = state_gradient(DEFAULT_LIFE_COLOR, DEFAULT_DEAD_COLOR, rule.states());
+        let state_color_buffer = color_buffer(&compute_queue, &state_colors);
+        let age_coloring = AgeColoring::default();
+        let age_color_buffer = color_buffer(&compute_queue, &age_coloring.shader_colors());
+        let theme = PaletteTheme::default().colors();
+        let palette_color_buffer = color_buffer(&compute_queue, &palette_colors(&theme));
+        let lut = lut_texture(compute_queue.clone(), &theme.gradient);
+        let theme_luts = HashMap::from([(PaletteTheme::default(), lut.clone())]);
+        let lut_sampler = Sampler::new(
+            compute_queue.device().clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
             },
         )
         .unwrap();
@@ -85,126 +527,1093 @@ impl GameOfLife {
             life_in,
             life_out,
             image,
+            size,
+            storage,
             sim_steps: 0,
+            pending_edits: VecDeque::new(),
+            pending_grid: None,
+            edit_pass: EditPass::new(compute_queue.clone()),
+            history: EditHistory::default(),
+            edit_readbacks: vec![],
+            rule,
+            boundary_mode: BoundaryMode::default(),
+            seed,
+            rng,
+            state_colors,
+            state_color_buffer,
+            ages,
+            age_coloring,
+            age_color_buffer,
+            color_mode: ColorMode::default(),
+            palette_color_buffer,
+            theme: None,
+            lut,
+            theme_luts,
+            lut_sampler,
+            compute_stats_pipeline,
+            stats_enabled: false,
+            stats_readbacks: vec![],
+            submissions: vec![],
         }
     }
 
+    /// Image the grid is colored into. Same size as the grid, except for large packed grids
+    /// where each pixel shows the density of live cells in a block of cells.
     pub fn color_image(&self) -> DeviceImageView {
         self.image.clone()
     }
 
+    /// Grid size in cells
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Resize the grid keeping its cells. Cells are cropped or padded with dead cells around
+    /// `anchor`. Buffers & color image are reallocated, everything else is kept. Queued edits
+    /// are applied first, blocking until the GPU has finished them and any steps submitted
+    /// without waiting, and the resized grid is colored.
+    pub fn resize(&mut self, size: [u32; 2], anchor: ResizeAnchor) {
+        assert!(size[0] > 0 && size[1] > 0, "Grid size must be positive");
+        if size == self.size {
+            return;
+        }
+        // Waits for the GPU, so that ages can be read too
+        let cells = self.read_grid();
+        let old_ages = match self.storage {
+            GridStorage::Unpacked => self
+                .ages
+                .read()
+                .expect("Ages are in use by the GPU")
+                .to_vec(),
+            GridStorage::Packed => vec![],
+        };
+        let offset = anchor.offset(self.size, size);
+        let mut grid = vec![0; self.storage.buffer_len(size) as usize];
+        // Padding is long dead
+        let mut ages = grid_ages(&grid, self.storage);
+        for (i, state) in cells.iter().enumerate() {
+            let pos = IVec2::new(
+                (i as u32 % self.size[0]) as i32,
+                (i as u32 / self.size[0]) as i32,
+            ) + offset;
+            if pos.x < 0 || pos.y < 0 || pos.x >= size[0] as i32 || pos.y >= size[1] as i32 {
+                continue;
+            }
+            if *state != 0 {
+                self.storage.write_cell(&mut grid, pos, size, *state as u32);
+            }
+            if let Some(age) = old_ages.get(i) {
+                ages[(pos.y * size[0] as i32 + pos.x) as usize] = *age;
+            }
+        }
+
+        // Previous step is the same as current, so cells count as survivors
+        self.life_in = grid_buffer(&self.compute_queue, grid.clone());
+        self.life_out = grid_buffer(&self.compute_queue, grid);
+        self.image = color_image(&self.compute_queue, size, self.storage);
+        self.ages = age_buffer(&self.compute_queue, ages);
+        self.size = size;
+        self.compute_steps(0);
+        // Cell indices of diffs changed
+        self.clear_history();
+    }
+
+    pub fn storage(&self) -> GridStorage {
+        self.storage
+    }
+
+    pub fn rule(&self) -> LifeRule {
+        self.rule
+    }
+
+    /// Set the rule used for the following steps. Takes effect on next `compute`.
+    /// If the number of states changes, state colors are regenerated from life & dead colors.
+    /// Fails if the grid storage doesn't support the rule, see `GridStorage::supports`.
+    pub fn set_rule(&mut self, rule: LifeRule) -> Result<(), UnsupportedRule> {
+        if !self.storage.supports(&rule) {
+            return Err(UnsupportedRule {
+                rule,
+                reason: "packed grid storage supports two state B/S rules only",
+            });
+        }
+        if rule.states() != self.rule.states() {
+            let life_color = self.state_colors[1];
+            let dead_color = self.state_colors[0];
+            self.rule = rule;
+            self.set_colors(life_color, dead_color);
+        } else {
+            self.rule = rule;
+        }
+        Ok(())
+    }
+
+    pub fn state_colors(&self) -> &[[f32; 4]] {
+        &self.state_colors
+    }
+
+    /// Set color for live and dead cells. Dying states of Generations rules get a gradient between
+    /// the two.
+    pub fn set_colors(&mut self, life_color: [f32; 4], dead_color: [f32; 4]) {
+        let colors = state_gradient(life_color, dead_color, self.rule.states());
+        self.set_state_colors(&colors);
+    }
+
+    /// Set a color for each cell state, starting from dead. States beyond given colors
+    /// use the last color.
+    pub fn set_state_colors(&mut self, colors: &[[f32; 4]]) {
+        assert!(
+            colors.len() >= 2,
+            "Need colors at least for dead and live states"
+        );
+        self.state_colors = colors.to_vec();
+        // New buffer instead of writing, the previous one may still be in use by the GPU
+        self.state_color_buffer = color_buffer(&self.compute_queue, colors);
+    }
+
+    /// Age coloring, if cells are colored by age
+    pub fn age_coloring(&self) -> Option<&AgeColoring> {
+        match self.color_mode {
+            ColorMode::Age => Some(&self.age_coloring),
+            _ => None,
+        }
+    }
+
+    /// Color cells by age instead of by state, or back by state with `None`. Dying states of
+    /// Generations rules keep their state colors. Ages are tracked with unpacked storage only.
+    pub fn set_age_coloring(&mut self, age_coloring: Option<AgeColoring>) {
+        match age_coloring {
+            Some(age_coloring) => {
+                self.set_color_mode(ColorMode::Age);
+                self.age_color_buffer =
+                    color_buffer(&self.compute_queue, &age_coloring.shader_colors());
+                self.age_coloring = age_coloring;
+            }
+            None if self.color_mode == ColorMode::Age => self.set_color_mode(ColorMode::State),
+            None => {}
+        }
+    }
+
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    fn set_color_mode(&mut self, color_mode: ColorMode) {
+        assert!(
+            self.storage == GridStorage::Unpacked || color_mode == ColorMode::State,
+            "Packed storage only supports coloring by state"
+        );
+        self.color_mode = color_mode;
+    }
+
+    /// Apply palette's mode & theme colors. Packed grids stay colored by state, with the
+    /// theme's colors. Theme colors replace state colors & age coloring colors only when the
+    /// theme changes, so colors set since are kept when e.g. only the mode changes. The first
+    /// use of a theme's LUT blocks until it's uploaded, after that only buffers & push constants
+    /// change.
+    pub fn set_palette(&mut self, palette: &LifePalette) {
+        let theme = palette.theme.colors();
+        let theme_changed = self.theme != Some(palette.theme);
+        self.theme = Some(palette.theme);
+        if theme_changed {
+            self.set_colors(theme.alive, theme.dead);
+        }
+        if self.storage == GridStorage::Packed {
+            return;
+        }
+        self.set_color_mode(palette.mode);
+        if theme_changed {
+            self.age_coloring = AgeColoring {
+                gradient: theme.gradient.clone(),
+                trail_color: theme.trail,
+                ..self.age_coloring.clone()
+            };
+            self.age_color_buffer =
+                color_buffer(&self.compute_queue, &self.age_coloring.shader_colors());
+            self.palette_color_buffer = color_buffer(&self.compute_queue, &palette_colors(&theme));
+        }
+        self.lut = match &palette.lut {
+            Some(lut) => lut.clone(),
+            None => {
+                let queue = &self.compute_queue;
+                self.theme_luts
+                    .entry(palette.theme)
+                    .or_insert_with(|| lut_texture(queue.clone(), &theme.gradient))
+                    .clone()
+            }
+        };
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Replace the grid with one from the initializer. Random initializers continue the RNG of
+    /// brushes. Written before the next step, see `set_grid`.
+    pub fn reset(&mut self, initializer: &GridInitializer) {
+        let grid = self
+            .storage
+            .pack_initializer(initializer, self.size, &mut self.rng);
+        self.upload_grid(grid);
+    }
+
+    /// Restart random numbers of brushes from seed. The grid is kept.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = LifeRng::seed_from_u64(seed);
+    }
+
+    pub fn boundary_mode(&self) -> BoundaryMode {
+        self.boundary_mode
+    }
+
+    /// Set the topology of grid edges. Applies to both simulation and drawing.
+    pub fn set_boundary_mode(&mut self, boundary_mode: BoundaryMode) {
+        self.boundary_mode = boundary_mode;
+    }
+
+    pub fn stats_enabled(&self) -> bool {
+        self.stats_enabled
+    }
+
+    /// Whether alive, birth & death counts are reduced on the GPU after each step. Off by default.
+    pub fn set_stats_enabled(&mut self, enabled: bool) {
+        self.stats_enabled = enabled;
+        if !enabled {
+            self.stats_readbacks.clear();
+        }
+    }
+
+    /// Stats of steps the GPU has finished since last call, oldest first. Steps must have been
+    /// flushed to the GPU, e.g. by presenting the frame they were chained to.
+    pub fn take_stats(&mut self) -> Vec<GenerationStats> {
+        let mut stats = vec![];
+        while let Some(readback) = self.stats_readbacks.first() {
+            // Fails while the GPU is still using the buffer
+            let counts = match readback.buffer.read() {
+                Ok(counts) => counts,
+                Err(_) => break,
+            };
+            for (i, c) in counts.chunks_exact(3).enumerate() {
+                stats.push(GenerationStats {
+                    generation: readback.first_generation + i as u32,
+                    alive: c[0],
+                    births: c[1],
+                    deaths: c[2],
+                });
+            }
+            drop(counts);
+            self.stats_readbacks.remove(0);
+        }
+        stats
+    }
+
+    /// Draw random life in a circle around pos, see `draw_brush`
     pub fn draw_life(&mut self, pos: IVec2, radius: i32) {
-        let mut life_in = {
-            if self.sim_steps % 2 == 0 {
-                self.life_out.write().unwrap()
-            } else {
-                self.life_in.write().unwrap()
+        self.draw_brush(&Brush::spray(radius.max(0) as u32), pos, pos);
+    }
+
+    /// Draw with brush moving from `from` to `to`, see `Brush::cells`. Queued as an edit command,
+    /// see `edit`.
+    pub fn draw_brush(&mut self, brush: &Brush, from: IVec2, to: IVec2) {
+        // Same random numbers as `Brush::cells`, so that it draws the same cells as the CPU
+        let seed = self.rng.gen();
+        if let Some(command) = brush.edit_command(from, to, self.size, seed) {
+            let group = self.history.edit_group();
+            self.queue_seeded_edit(command, seed, Some(group));
+        }
+    }
+
+    /// Stamp pattern to the grid with its bottom left corner at `pos`. All cells in the pattern's
+    /// bounding box are overwritten. Cells over the edges are resolved by the boundary mode.
+    /// Queued as edit commands forming one undo step, see `edit`.
+    pub fn stamp_pattern(&mut self, pattern: &Pattern, pos: IVec2) {
+        let size = pattern.size();
+        if size[0] == 0 || size[1] == 0 {
+            return;
+        }
+        // Clearing the box and then setting the live cells keeps the edit as large as the live
+        // cells rather than the box
+        let max = IVec2::new(
+            pos.x.saturating_add(size[0] as i32 - 1),
+            pos.y.saturating_add(size[1] as i32 - 1),
+        );
+        self.begin_undo_group();
+        self.edit(EditCommand::Rect {
+            min: pos,
+            max,
+            mode: BrushMode::Clear,
+        });
+        self.edit(EditCommand::Cells(pattern.grid_cells(pos)));
+        self.end_undo_group();
+    }
+
+    /// Queue an edit of the grid. Edits are applied in order by a compute pass before the next
+    /// step, so they never race with the GPU. See `apply_edits` to apply them without stepping.
+    /// Edits can be undone, see `undo`.
+    pub fn edit(&mut self, command: EditCommand) {
+        let group = self.history.edit_group();
+        self.queue_edit(command, Some(group));
+    }
+
+    fn queue_edit(&mut self, command: EditCommand, undo_group: Option<u64>) {
+        let seed = self.rng.gen();
+        self.queue_seeded_edit(command, seed, undo_group);
+    }
+
+    fn queue_seeded_edit(&mut self, command: EditCommand, seed: u32, undo_group: Option<u64>) {
+        self.pending_edits.push_back(PendingEdit::Edit(QueuedEdit {
+            command,
+            seed,
+            undo_group,
+        }));
+    }
+
+    /// Undo the latest undo step, i.e. an edit or a group of edits. Cells it changed get their
+    /// previous states back, while the rest of the grid keeps any steps computed since. Queued
+    /// like edits, and applied once the diffs of the edits queued before it have arrived, so it
+    /// never blocks. Does nothing if there's nothing to undo by then.
+    pub fn undo(&mut self) {
+        self.pending_edits
+            .push_back(PendingEdit::History(HistoryCommand::Undo));
+    }
+
+    /// Redo the latest undone step, see `undo`
+    pub fn redo(&mut self) {
+        self.pending_edits
+            .push_back(PendingEdit::History(HistoryCommand::Redo));
+    }
+
+    /// Whether there's an undo step. Steps only count once the diffs of their edits have arrived
+    /// from the GPU, and queued undo steps aren't taken into account.
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Group the following edits into a single undo step until `end_undo_group`, e.g. for a
+    /// stroke. Nested groups join the outer group.
+    pub fn begin_undo_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// End the open undo group by reverting its edits instead of keeping them, e.g. when its
+    /// strokes turn out to be a gesture. Redo steps are lost.
+    pub fn discard_undo_group(&mut self) {
+        if let Some(group) = self.history.discard_group() {
+            self.pending_edits.retain(|pending| match pending {
+                PendingEdit::Edit(edit) => edit.undo_group != Some(group),
+                PendingEdit::History(_) => true,
+            });
+            self.collect_edit_diffs();
+        }
+    }
+
+    /// Take diffs of finished edits into the undo stack
+    fn collect_edit_diffs(&mut self) {
+        while let Some(readback) = self.edit_readbacks.first() {
+            // Fails while the GPU is still using the buffer
+            let diffs = match readback.diffs() {
+                Some(diffs) => diffs,
+                None => break,
+            };
+            self.edit_readbacks.remove(0);
+            self.history.finished(diffs);
+        }
+        if let Some(revert) = self.history.take_revert(self.size[0]) {
+            // Before edits queued since the discarded group
+            let seed = self.rng.gen();
+            self.pending_edits.push_front(PendingEdit::Edit(QueuedEdit {
+                command: revert,
+                seed,
+                undo_group: None,
+            }));
+        }
+    }
+
+    /// Forget undo & redo steps, and diffs on their way
+    fn clear_history(&mut self) {
+        self.history.clear();
+        self.edit_readbacks.clear();
+    }
+
+    /// Whether edits, undo steps or a grid are queued for the next submission
+    pub fn has_pending_edits(&self) -> bool {
+        !self.pending_edits.is_empty() || self.pending_grid.is_some()
+    }
+
+    /// Apply queued edits & color the grid without stepping, blocking until the GPU has finished.
+    /// Undo steps waiting for diffs stay queued, see `undo`.
+    pub fn apply_edits(&mut self) {
+        self.compute_steps(0);
+    }
+
+    /// Apply queued edits, including undo steps waiting for the diffs of edits before them, and
+    /// wait for the GPU, so that the grid can be read on the CPU
+    fn flush_edits(&mut self) {
+        // Diffs have arrived after the first pass, which blocks
+        for _ in 0..2 {
+            if !self.has_pending_edits() {
+                break;
+            }
+            self.apply_edits();
+        }
+        self.wait_for_gpu();
+    }
+
+    /// Live and dying cells of the region with its bottom left corner at `pos` as a pattern of
+    /// the region's size. Cells over the edges are resolved by the boundary mode. Queued edits
+    /// are applied first, see `grid`.
+    pub fn extract_pattern(&mut self, pos: IVec2, size: [u32; 2]) -> Pattern {
+        let grid = self.grid();
+        let mut cells = vec![];
+        for y in 0..size[1] as i32 {
+            for x in 0..size[0] as i32 {
+                // Pattern rows grow downwards, grid rows upwards
+                let grid_pos = pos + IVec2::new(x, size[1] as i32 - 1 - y);
+                if let Some(grid_pos) = self.boundary_mode.resolve(grid_pos, self.size) {
+                    let state = grid[(grid_pos.y * self.size[0] as i32 + grid_pos.x) as usize];
+                    if state != 0 {
+                        cells.push((IVec2::new(x, y), state));
+                    }
+                }
+            }
+        }
+        Pattern::with_states(size, cells)
+    }
+
+    /// Current board as RLE, cropped to the bounding box of live and dying cells. Queued edits
+    /// are applied first, see `grid`.
+    pub fn to_rle(&mut self) -> String {
+        self.region_to_rle(IVec2::ZERO, self.size)
+    }
+
+    /// Region with its bottom left corner at `pos` as RLE, cropped to the bounding box of live
+    /// and dying cells. Queued edits are applied first, see `grid`.
+    pub fn region_to_rle(&mut self, pos: IVec2, size: [u32; 2]) -> String {
+        let region = self.extract_pattern(pos, size);
+        let mut pattern = Pattern::from_cell_states(region.cell_states());
+        pattern.set_rule(Some(self.rule));
+        pattern.to_rle()
+    }
+
+    /// Cell states of the current step, row by row. Packed grids are unpacked to one state per
+    /// cell. Queued edits are applied first, blocking until the GPU has finished them and any
+    /// steps submitted without waiting.
+    pub fn grid(&mut self) -> Vec<u32> {
+        self.flush_edits();
+        let grid = self
+            .current_grid()
+            .read()
+            .expect("Grid is in use by the GPU");
+        (0..self.size[1] as i32)
+            .flat_map(|y| (0..self.size[0] as i32).map(move |x| IVec2::new(x, y)))
+            .map(|pos| self.storage.read_cell(&grid, pos, self.size))
+            .collect()
+    }
+
+    /// Replace cell states of the current step, row by row. Replaces queued edits, and is
+    /// applied like them before the next step. Can't be undone, and clears the undo history.
+    /// Ages start over from the new states.
+    pub fn set_grid(&mut self, cells: &[u32]) {
+        assert_eq!(
+            cells.len(),
+            (self.size[0] * self.size[1]) as usize,
+            "Grid must have a state for each cell"
+        );
+        let mut grid = Vec::with_capacity(self.storage.buffer_len(self.size) as usize);
+        for row in cells.chunks(self.size[0] as usize) {
+            self.storage.push_row(&mut grid, row);
+        }
+        self.upload_grid(grid);
+    }
+
+    /// Copy grid words to the current step before the next step, through a staging buffer so
+    /// that the GPU may still be using the grid. See `set_grid`.
+    fn upload_grid(&mut self, grid: Vec<u32>) {
+        self.pending_edits.clear();
+        self.clear_history();
+        // New buffer, the GPU may still be using the old one
+        self.ages = age_buffer(&self.compute_queue, grid_ages(&grid, self.storage));
+        let staging = CpuAccessibleBuffer::from_iter(
+            self.compute_queue.device().clone(),
+            BufferUsage::transfer_src(),
+            false,
+            grid,
+        )
+        .unwrap();
+        self.pending_grid = Some(staging);
+    }
+
+    /// Save the complete simulation state: grid, size, storage, step parity, rule, boundary mode,
+    /// state colors, seed & position of brush random numbers and ages. Queued edits are applied
+    /// first, blocking until the GPU has finished them and any steps submitted without waiting.
+    pub fn save(&mut self) -> Vec<u8> {
+        // Grid & ages are free once the GPU has finished
+        self.flush_edits();
+        let grid = self
+            .current_grid()
+            .read()
+            .expect("Grid is in use by the GPU")
+            .to_vec();
+        let mut writer = SaveWriter::new();
+        writer.write_u32(self.size[0]);
+        writer.write_u32(self.size[1]);
+        writer.write_u8(self.storage.as_u32() as u8);
+        writer.write_u8((self.sim_steps % 2) as u8);
+        writer.write_str(&self.rule.to_string());
+        writer.write_u8(self.boundary_mode.as_u32() as u8);
+        writer.write_u32(self.state_colors.len() as u32);
+        for color in self.state_colors.iter() {
+            for c in color {
+                writer.write_f32(*c);
+            }
+        }
+        for word in grid {
+            writer.write_u32(word);
+        }
+        writer.write_u64(self.seed);
+        let word_pos = self.rng.get_word_pos();
+        writer.write_u64(word_pos as u64);
+        writer.write_u64((word_pos >> 64) as u64);
+        if self.storage == GridStorage::Unpacked {
+            let ages = self.ages.read().expect("Ages are in use by the GPU");
+            for age in ages.iter() {
+                writer.write_u32(*age);
+            }
+        }
+        writer.finish()
+    }
+
+    /// Load a simulation saved with `save`, continuing exactly where it was left, and color it.
+    /// Saves from before seeds were saved get a random seed and start ages over.
+    pub fn load(compute_queue: Arc<Queue>, bytes: &[u8]) -> Result<GameOfLife, SaveError> {
+        let mut reader = SaveReader::new(bytes)?;
+        let size = [reader.read_u32()?, reader.read_u32()?];
+        if size[0] == 0 || size[1] == 0 {
+            return Err(SaveError::InvalidValue("grid size"));
+        }
+        let storage = GridStorage::from_u32(reader.read_u8()? as u32)
+            .ok_or(SaveError::InvalidValue("grid storage"))?;
+        let buffer_len = storage
+            .checked_buffer_len(size)
+            .ok_or(SaveError::InvalidValue("grid size"))?;
+        let sim_steps = reader.read_u8()? as u32;
+        if sim_steps > 1 {
+            return Err(SaveError::InvalidValue("step parity"));
+        }
+        let rule = reader
+            .read_str()?
+            .parse::<LifeRule>()
+            .map_err(SaveError::InvalidRule)?;
+        if !storage.supports(&rule) {
+            return Err(SaveError::InvalidValue("rule for grid storage"));
+        }
+        let boundary_mode = BoundaryMode::from_u32(reader.read_u8()? as u32)
+            .ok_or(SaveError::InvalidValue("boundary mode"))?;
+        let num_colors = reader.read_u32()?;
+        if num_colors < 2 {
+            return Err(SaveError::InvalidValue("state colors"));
+        }
+        let mut state_colors = vec![];
+        for _ in 0..num_colors {
+            state_colors.push([
+                reader.read_f32()?,
+                reader.read_f32()?,
+                reader.read_f32()?,
+                reader.read_f32()?,
+            ]);
+        }
+        let mut grid = vec![];
+        for _ in 0..buffer_len {
+            grid.push(reader.read_u32()?);
+        }
+        let mut seed = None;
+        // Ages of older saves start over
+        let mut ages = vec![];
+        if reader.version() >= 2 {
+            let saved_seed = reader.read_u64()?;
+            let word_pos = reader.read_u64()? as u128 | ((reader.read_u64()? as u128) << 64);
+            seed = Some((saved_seed, word_pos));
+            if storage == GridStorage::Unpacked {
+                for _ in 0..buffer_len {
+                    ages.push(reader.read_u32()?);
+                }
+            }
+        }
+        if !reader.is_finished() {
+            return Err(SaveError::InvalidValue("grid length"));
+        }
+
+        let mut game_of_life = GameOfLife::with_initializer(
+            compute_queue,
+            size,
+            storage,
+            seed.map_or_else(random_seed, |(seed, _)| seed),
+            &GridInitializer::Empty,
+        );
+        if let Some((_, word_pos)) = seed {
+            game_of_life.rng.set_word_pos(word_pos);
+        }
+        game_of_life.rule = rule;
+        game_of_life.boundary_mode = boundary_mode;
+        game_of_life.set_state_colors(&state_colors);
+        game_of_life.sim_steps = sim_steps;
+        // New buffers aren't in use by the GPU yet
+        game_of_life
+            .current_grid()
+            .write()
+            .unwrap()
+            .copy_from_slice(&grid);
+        if storage == GridStorage::Unpacked {
+            if ages.is_empty() {
+                ages = grid_ages(&grid, storage);
+            }
+            game_of_life.ages.write().unwrap().copy_from_slice(&ages);
+        }
+        game_of_life.apply_edits();
+        Ok(game_of_life)
+    }
+
+    /// Grid buffer of the current step
+    fn current_grid(&self) -> &Arc<CpuAccessibleBuffer<[u32]>> {
+        if self.sim_steps % 2 == 0 {
+            &self.life_out
+        } else {
+            &self.life_in
+        }
+    }
+
+    /// Record queued edits of the current step. Binds the edit pipeline. Undo steps are resolved
+    /// to edits here, so that they're applied in the submission chained after earlier ones.
+    fn record_edits(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        self.collect_edit_diffs();
+        if let Some(grid) = self.pending_grid.take() {
+            builder
+                .copy_buffer(CopyBufferInfo::buffers(grid, self.current_grid().clone()))
+                .unwrap();
+        }
+        let mut edits = vec![];
+        while let Some(pending) = self.pending_edits.pop_front() {
+            let command = match pending {
+                PendingEdit::Edit(edit) => {
+                    edits.push(edit);
+                    continue;
+                }
+                PendingEdit::History(command) => command,
+            };
+            // Undo steps are complete once the diffs of earlier edits have arrived
+            if self.history.is_waiting() || edits.iter().any(|edit| edit.undo_group.is_some()) {
+                self.pending_edits.push_front(PendingEdit::History(command));
+                break;
+            }
+            let edit = match command {
+                HistoryCommand::Undo => self.history.undo(self.size[0]),
+                HistoryCommand::Redo => self.history.redo(self.size[0]),
+            };
+            if let Some(edit) = edit {
+                edits.push(QueuedEdit {
+                    command: edit,
+                    seed: self.rng.gen(),
+                    undo_group: None,
+                });
+            }
+        }
+        let readback = self.edit_pass.record(
+            builder,
+            self.current_grid().clone(),
+            self.ages.clone(),
+            &edits,
+            self.size,
+            self.storage == GridStorage::Packed,
+            self.boundary_mode,
+        );
+        if let Some(readback) = readback {
+            self.history.submitted(readback.groups().to_vec());
+            self.edit_readbacks.push(readback);
+        }
+    }
+
+    /// Compute next step & color it, blocking until the GPU has finished
+    pub fn compute(&mut self) {
+        self.compute_steps(1);
+    }
+
+    /// Compute next step colored with `life_color` & `dead_color`, blocking until the GPU has
+    /// finished. `compute` used to take the colors on every step, now they're kept by
+    /// `set_colors` and only uploaded when they change, which this does too.
+    #[deprecated(note = "set colors with `set_colors` and step with `compute`")]
+    pub fn compute_with_colors(&mut self, life_color: [f32; 4], dead_color: [f32; 4]) {
+        let colors = state_gradient(life_color, dead_color, self.rule.states());
+        if colors != self.state_colors {
+            self.set_state_colors(&colors);
+        }
+        self.compute();
+    }
+
+    /// Compute next `steps` steps & color the last one, blocking until the GPU has finished
+    pub fn compute_steps(&mut self, steps: u32) {
+        self.wait_for_gpu();
+        let before = sync::now(self.compute_queue.device().clone());
+        let _ = self.compute_steps_after(before, steps);
+        self.wait_for_gpu();
+    }
+
+    /// Submit after `before`, flushing right away so that blocking calls can wait for it
+    fn submit<F>(
+        &mut self,
+        before: F,
+        command_buffer: PrimaryAutoCommandBuffer,
+    ) -> Box<dyn GpuFuture>
+    where
+        F: GpuFuture + 'static,
+    {
+        // Waiting on finished submissions only releases their buffers
+        self.submissions.retain(|submission| {
+            let finished = submission.is_signaled().unwrap_or(false);
+            if finished {
+                submission.wait(None).unwrap();
+            }
+            !finished
+        });
+        let after = before
+            .then_execute(self.compute_queue.clone(), command_buffer)
+            .unwrap()
+            .boxed()
+            .then_signal_fence_and_flush()
+            .unwrap();
+        let after = Arc::new(after);
+        self.submissions.push(after.clone());
+        after.boxed()
+    }
+
+    /// Block until all submissions have finished, e.g. steps submitted without waiting, so that
+    /// buffers can be used on the CPU
+    fn wait_for_gpu(&mut self) {
+        for submission in self.submissions.drain(..) {
+            submission.wait(None).unwrap();
+        }
+    }
+
+    /// Compute next step & color it after `before`, without waiting for the GPU. Chain the
+    /// returned future with rendering of `color_image`, e.g. `FillScreenRenderPass::draw`.
+    pub fn compute_after<F>(&mut self, before: F) -> Box<dyn GpuFuture>
+    where
+        F: GpuFuture + 'static,
+    {
+        self.compute_steps_after(before, 1)
+    }
+
+    /// Compute next `steps` steps & color the last one after `before`, without waiting for the
+    /// GPU. All steps are recorded into a single command buffer, so fast forwarding costs a
+    /// single submission.
+    pub fn compute_steps_after<F>(&mut self, before: F, steps: u32) -> Box<dyn GpuFuture>
+    where
+        F: GpuFuture + 'static,
+    {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.compute_queue.device().clone(),
+            self.compute_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        // Edits come first, so that the step sees them
+        self.record_edits(&mut builder);
+
+        // Dispatch will mutate the builder adding commands which won't be sent before we build the command buffer
+        // after dispatches. This will minimize the commands we send to the GPU. The builder inserts
+        // barriers between the dispatches, so each step sees the full output of the previous one.
+        let life_set = self.life_descriptor_set();
+        self.bind(&mut builder, life_set.clone());
+
+        // Counts of each step are reduced into their own slot
+        let stats = if self.stats_enabled && steps > 0 {
+            let buffer = CpuAccessibleBuffer::from_iter(
+                self.compute_queue.device().clone(),
+                BufferUsage::all(),
+                true,
+                (0..steps * 3).map(|_| 0u32),
+            )
+            .unwrap();
+            if self.stats_readbacks.len() == MAX_STATS_READBACKS {
+                self.stats_readbacks.remove(0);
             }
+            self.stats_readbacks.push(StatsReadback {
+                buffer: buffer.clone(),
+                first_generation: self.sim_steps + 1,
+            });
+            Some(buffer)
+        } else {
+            None
         };
-        let size = self.image.image().dimensions().width_height();
-        if pos.y < 0 || pos.y >= size[1] as i32 || pos.x < 0 || pos.x >= size[0] as i32 {
-            return;
-        }
-        let y_start = pos.y - radius;
-        let y_end = pos.y + radius;
-        let x_start = pos.x - radius;
-        let x_end = pos.x + radius;
-        for y in y_start..=y_end {
-            for x in x_start..=x_end {
-                let world_pos = bevy::math::Vec2::new(x as f32, y as f32);
-                if world_pos
-                    .distance(bevy::math::Vec2::new(pos.x as f32, pos.y as f32))
-                    .round()
-                    <= radius as f32
-                {
-                    let pos = world_pos.as_ivec2();
-                    if pos.y < 0 || pos.y >= size[1] as i32 || pos.x < 0 || pos.x >= size[0] as i32
-                    {
-                        continue;
-                    }
-                    let index = (pos.y * size[0] as i32 + pos.x) as usize;
-                    if rand::thread_rng().gen::<f32>() > 0.5 {
-                        life_in[index] = 1
-                    };
-                }
+
+        // First compute the next states. Swap buffers each step
+        for i in 0..steps {
+            self.dispatch(&mut builder, 0, self.sim_steps % 2 == 0);
+            self.sim_steps += 1;
+            if let Some(stats) = &stats {
+                self.dispatch_stats(&mut builder, stats.clone(), i);
+                self.bind(&mut builder, life_set.clone());
             }
         }
+
+        // Then color based on the last state. Read from the buffer we just wrote to
+        self.dispatch(&mut builder, 1, self.sim_steps % 2 == 1);
+
+        let command_buffer = builder.build().unwrap();
+        self.submit(before, command_buffer)
+    }
+
+    /// Copy the current step to the CPU, blocking until the copy has finished. One state per
+    /// cell, row by row, including queued edits. Steps submitted without waiting are waited for
+    /// first.
+    pub fn read_grid(&mut self) -> Vec<u8> {
+        self.wait_readback().cells().unwrap()
+    }
+
+    /// Copy the current step to the CPU as live cell bits, blocking until the GPU has finished.
+    /// See `GridReadback::bitset`.
+    pub fn read_grid_bitset(&mut self) -> Vec<u32> {
+        self.wait_readback().bitset().unwrap()
+    }
+
+    fn wait_readback(&mut self) -> GridReadback {
+        self.wait_for_gpu();
+        let before = sync::now(self.compute_queue.device().clone());
+        let (_, readback) = self.read_grid_after(before);
+        self.wait_for_gpu();
+        readback
     }
 
-    pub fn compute(&mut self, life_color: [f32; 4], dead_color: [f32; 4]) {
+    /// Copy the current step to a staging buffer after `before`, without waiting for the GPU.
+    /// Queued edits are included. The copy only waits for what `before` waits for, so steps
+    /// submitted without waiting must either have finished or be chained in by passing their
+    /// future, e.g. the one returned by `compute_steps_after`. Cells can be read from the
+    /// returned readback once the returned future has finished.
+    pub fn read_grid_after<F>(&mut self, before: F) -> (Box<dyn GpuFuture>, GridReadback)
+    where
+        F: GpuFuture + 'static,
+    {
+        let staging = CpuAccessibleBuffer::from_iter(
+            self.compute_queue.device().clone(),
+            BufferUsage::transfer_dst(),
+            true,
+            (0..self.storage.buffer_len(self.size)).map(|_| 0u32),
+        )
+        .unwrap();
+
         let mut builder = AutoCommandBufferBuilder::primary(
             self.compute_queue.device().clone(),
             self.compute_queue.family(),
             CommandBufferUsage::OneTimeSubmit,
         )
         .unwrap();
-
-        // Dispatch will mutate the builder adding commands which won't be sent before we build the command buffer
-        // after dispatches. This will minimize the commands we send to the GPU. For example, we could be doing
-        // tens of dispatches here depending on our needs. Maybe we wanted to simulate 10 steps at a time...
-
-        // First compute the next state. Swap buffers
-        self.dispatch(
-            &mut builder,
-            life_color,
-            dead_color,
-            0,
-            self.sim_steps % 2 == 0,
-        );
-
-        // Then color based on the next state. Don't swap buffers
-        self.dispatch(&mut builder, life_color, dead_color, 1, false);
-
+        self.record_edits(&mut builder);
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(
+                self.current_grid().clone(),
+                staging.clone(),
+            ))
+            .unwrap();
         let command_buffer = builder.build().unwrap();
+        let after = self.submit(before, command_buffer);
+        let readback = GridReadback {
+            buffer: staging,
+            size: self.size,
+            storage: self.storage,
+        };
+        (after, readback)
+    }
 
-        let finished = command_buffer.execute(self.compute_queue.clone()).unwrap();
-        let _ = finished
-            .then_signal_fence_and_flush()
-            .unwrap()
-            .wait(None)
-            .unwrap();
-        self.sim_steps += 1;
+    /// Descriptor set of the life pipeline
+    fn life_descriptor_set(&self) -> Arc<PersistentDescriptorSet> {
+        let pipeline_layout = self.compute_life_pipeline.layout();
+        let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
+        let mut writes = vec![
+            WriteDescriptorSet::image_view(0, self.image.clone()),
+            WriteDescriptorSet::buffer(1, self.life_in.clone()),
+            WriteDescriptorSet::buffer(2, self.life_out.clone()),
+            WriteDescriptorSet::buffer(3, self.state_color_buffer.clone()),
+        ];
+        if self.storage == GridStorage::Unpacked {
+            writes.push(WriteDescriptorSet::buffer(4, self.ages.clone()));
+            writes.push(WriteDescriptorSet::buffer(5, self.age_color_buffer.clone()));
+            writes.push(WriteDescriptorSet::buffer(
+                6,
+                self.palette_color_buffer.clone(),
+            ));
+            writes.push(WriteDescriptorSet::image_view_sampler(
+                7,
+                self.lut.clone(),
+                self.lut_sampler.clone(),
+            ));
+        }
+        PersistentDescriptorSet::new(desc_layout.clone(), writes).unwrap()
     }
 
-    /// Build the command for a dispatch.
-    fn dispatch(
-        &mut self,
+    /// Bind life pipeline & descriptor set for following dispatches
+    fn bind(
+        &self,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-        life_color: [f32; 4],
-        dead_color: [f32; 4],
-        // Step determines whether we color or compute life (see branch in the shader)s
-        step: i32,
-        swap_read_order: bool,
+        set: Arc<PersistentDescriptorSet>,
     ) {
-        // Resize image if needed
-        let img_dims = self.image.image().dimensions().width_height();
         let pipeline_layout = self.compute_life_pipeline.layout();
+        builder
+            .bind_pipeline_compute(self.compute_life_pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set);
+    }
+
+    /// Reduce counts of the step just computed into `slot` of stats buffer. Binds the stats
+    /// pipeline, so life pipeline must be bound again after.
+    fn dispatch_stats(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        stats: Arc<CpuAccessibleBuffer<[u32]>>,
+        slot: u32,
+    ) {
+        let pipeline_layout = self.compute_stats_pipeline.layout();
         let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
         let set = PersistentDescriptorSet::new(
             desc_layout.clone(),
             [
-                WriteDescriptorSet::image_view(0, self.image.clone()),
-                WriteDescriptorSet::buffer(1, self.life_in.clone()),
-                WriteDescriptorSet::buffer(2, self.life_out.clone()),
+                WriteDescriptorSet::buffer(0, self.life_in.clone()),
+                WriteDescriptorSet::buffer(1, self.life_out.clone()),
+                WriteDescriptorSet::buffer(2, stats),
             ],
         )
         .unwrap();
+        let len = self.storage.buffer_len(self.size);
+        let push_constants = compute_stats_cs::ty::PushConstants {
+            // Step wrote to life_in when sim_steps became odd
+            current_is_in: self.sim_steps % 2,
+            packed: (self.storage == GridStorage::Packed) as u32,
+            len,
+            slot,
+        };
+        builder
+            .bind_pipeline_compute(self.compute_stats_pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
+            .push_constants(pipeline_layout.clone(), 0, push_constants)
+            .dispatch([(len + STATS_LOCAL_SIZE - 1) / STATS_LOCAL_SIZE, 1, 1])
+            .unwrap();
+    }
 
+    /// Build the command for a dispatch. Pipeline must be bound with `bind`
+    fn dispatch(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        // Step determines whether we color or compute life (see branch in the shader)s
+        step: i32,
+        swap_read_order: bool,
+    ) {
+        let img_dims = self.image.image().dimensions().width_height();
+        let pipeline_layout = self.compute_life_pipeline.layout();
+        if self.storage == GridStorage::Packed {
+            // Life step runs per word, coloring per pixel
+            let dispatch_dims = if step == 0 {
+                [words_per_row(self.size[0]), self.size[1]]
+            } else {
+                img_dims
+            };
+            let push_constants = compute_packed_life_cs::ty::PushConstants {
+                step,
+                swap_read_order: swap_read_order as u32,
+                birth_mask: self.rule.birth_mask(),
+                survival_mask: self.rule.survival_mask(),
+                boundary_mode: self.boundary_mode.as_u32(),
+                width: self.size[0],
+                height: self.size[1],
+                words_per_row: words_per_row(self.size[0]),
+                image_scale: packed_image_scale(self.size),
+            };
+            builder
+                .push_constants(pipeline_layout.clone(), 0, push_constants)
+                .dispatch([(dispatch_dims[0] + 7) / 8, (dispatch_dims[1] + 7) / 8, 1])
+                .unwrap();
+            return;
+        }
         let push_constants = compute_life_cs::ty::PushConstants {
-            life_color,
-            dead_color,
             step,
             swap_read_order: swap_read_order as u32,
+            birth_mask: self.rule.birth_mask(),
+            survival_mask: self.rule.survival_mask(),
+            boundary_mode: self.boundary_mode.as_u32(),
+            num_states: self.rule.states(),
+            radius: self.rule.radius(),
+            neighbourhood: self.rule.neighbourhood().as_u32(),
+            include_center: self.rule.include_center() as u32,
+            birth_min: self.rule.birth_range().0,
+            birth_max: self.rule.birth_range().1,
+            survival_min: self.rule.survival_range().0,
+            survival_max: self.rule.survival_range().1,
+            color_mode: self.color_mode.as_u32(),
+            lut_input: match self.color_mode {
+                ColorMode::Lut(input) => input.as_u32(),
+                _ => LutInput::default().as_u32(),
+            },
+            max_age: self.age_coloring.max_age,
+            trail_length: self.age_coloring.trail_length,
         };
         builder
-            .bind_pipeline_compute(self.compute_life_pipeline.clone())
-            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
             .push_constants(pipeline_layout.clone(), 0, push_constants)
-            .dispatch([img_dims[0] / 8, img_dims[1] / 8, 1])
+            .dispatch([(img_dims[0] + 7) / 8, (img_dims[1] + 7) / 8, 1])
             .unwrap();
     }
 }
 
+impl LifeSimulation for GameOfLife {
+    fn size(&self) -> [u32; 2] {
+        GameOfLife::size(self)
+    }
+
+    fn rule(&self) -> LifeRule {
+        GameOfLife::rule(self)
+    }
+
+    fn set_rule(&mut self, rule: LifeRule) -> Result<(), UnsupportedRule> {
+        GameOfLife::set_rule(self, rule)
+    }
+
+    fn boundary_mode(&self) -> BoundaryMode {
+        GameOfLife::boundary_mode(self)
+    }
+
+    fn set_boundary_mode(&mut self, boundary_mode: BoundaryMode) {
+        GameOfLife::set_boundary_mode(self, boundary_mode)
+    }
+
+    fn seed(&self) -> u64 {
+        GameOfLife::seed(self)
+    }
+
+    fn draw_life(&mut self, pos: IVec2, radius: i32) {
+        GameOfLife::draw_life(self, pos, radius)
+    }
+
+    fn draw_brush(&mut self, brush: &Brush, from: IVec2, to: IVec2) {
+        GameOfLife::draw_brush(self, brush, from, to)
+    }
+
+    fn compute_steps(&mut self, steps: u32) {
+        GameOfLife::compute_steps(self, steps)
+    }
+
+    fn grid(&mut self) -> Vec<u32> {
+        GameOfLife::grid(self)
+    }
+
+    fn set_grid(&mut self, cells: &[u32]) {
+        GameOfLife::set_grid(self, cells)
+    }
+}
+
 mod compute_life_cs {
     vulkano_shaders::shader! {
         ty: "compute",
@@ -216,19 +1625,106 @@ layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
 layout(set = 0, binding = 0, rgba8) uniform writeonly image2D img;
 layout(set = 0, binding = 1) buffer LifeInBuffer { uint life_in[]; };
 layout(set = 0, binding = 2) buffer LifeOutBuffer { uint life_out[]; };
+layout(set = 0, binding = 3) readonly buffer StateColorBuffer { vec4 state_colors[]; };
+// Generations in the current status, with AGE_ALIVE_BIT set if the status is alive
+layout(set = 0, binding = 4) buffer AgeBuffer { uint ages[]; };
+// Age gradient followed by trail color
+layout(set = 0, binding = 5) readonly buffer AgeColorBuffer { vec4 age_colors[]; };
+// Dead, birth & survivor colors followed by theme gradient
+layout(set = 0, binding = 6) readonly buffer PaletteColorBuffer { vec4 palette_colors[]; };
+layout(set = 0, binding = 7) uniform sampler1D lut;
 
 layout(push_constant) uniform PushConstants {
-    vec4 life_color;
-    vec4 dead_color;
     int step;
     bool swap_read_order;
+    // Bit n is set if n live neighbours cause birth / survival
+    uint birth_mask;
+    uint survival_mask;
+    // See BoundaryMode
+    uint boundary_mode;
+    // 2 for Life-like rules, more for Generations
+    uint num_states;
+    // Larger than Life, 1 & Moore for B/S rules. Neighbourhood 0 is Moore, 1 von Neumann
+    uint radius;
+    uint neighbourhood;
+    bool include_center;
+    // Inclusive ranges of counts, empty for B/S rules
+    uint birth_min;
+    uint birth_max;
+    uint survival_min;
+    uint survival_max;
+    // See ColorMode & LutInput
+    uint color_mode;
+    uint lut_input;
+    // See AgeColoring
+    uint max_age;
+    uint trail_length;
 } push_constants;
 
+#define COLOR_STATE 0
+#define COLOR_AGE 1
+#define COLOR_NEIGHBOUR_COUNT 2
+#define COLOR_BIRTHS_AND_SURVIVORS 3
+#define COLOR_LUT 4
+
+#define LUT_STATE 0
+#define LUT_NEIGHBOUR_COUNT 1
+
+#define PALETTE_BIRTH 1
+#define PALETTE_SURVIVOR 2
+#define PALETTE_GRADIENT 3
+
+#define AGE_ALIVE_BIT 0x80000000u
+#define AGE_MAX 0x7fffffffu
+
+#define BOUNDARY_DEAD 0
+#define BOUNDARY_TORUS 1
+#define BOUNDARY_MIRROR 2
+#define BOUNDARY_KLEIN_BOTTLE 3
+#define BOUNDARY_PROJECTIVE_PLANE 4
+
 int get_index(ivec2 pos) {
     ivec2 dims = ivec2(imageSize(img));
     return pos.y * dims.x + pos.x;
 }
 
+// Integer % is undefined for negative operands in GLSL, thus floor division
+ivec2 floor_div(ivec2 a, ivec2 b) {
+    return ivec2(floor(vec2(a) / vec2(b)));
+}
+
+int reflect_coord(int v, int len) {
+    int m = v - 2 * len * floor_div(ivec2(v), ivec2(2 * len)).x;
+    return m >= len ? 2 * len - 1 - m : m;
+}
+
+// Map position outside of the grid back onto the grid based on boundary mode.
+// Returns false if the position lies on a dead boundary. Must match BoundaryMode::resolve
+bool resolve_pos(inout ivec2 pos) {
+    ivec2 dims = ivec2(imageSize(img));
+    if (pos.x >= 0 && pos.x < dims.x && pos.y >= 0 && pos.y < dims.y) {
+        return true;
+    }
+    if (push_constants.boundary_mode == BOUNDARY_DEAD) {
+        return false;
+    }
+    if (push_constants.boundary_mode == BOUNDARY_MIRROR) {
+        pos = ivec2(reflect_coord(pos.x, dims.x), reflect_coord(pos.y, dims.y));
+        return true;
+    }
+    // Odd number of crossings over an edge flips the other axis in non-orientable topologies
+    ivec2 wraps = floor_div(pos, dims);
+    ivec2 wrapped = pos - wraps * dims;
+    if (push_constants.boundary_mode != BOUNDARY_TORUS && (wraps.y & 1) != 0) {
+        wrapped.x = dims.x - 1 - wrapped.x;
+    }
+    if (push_constants.boundary_mode == BOUNDARY_PROJECTIVE_PLANE && (wraps.x & 1) != 0) {
+        wrapped.y = dims.y - 1 - wrapped.y;
+    }
+    pos = wrapped;
+    return true;
+}
+
 // On iOS it seems that std::mem::swap for buffers causes
 // GPU Address Fault Error (0000000b:kIOGPUCommandBufferCallbackErrorPageFault)
 // Thus I'll just read and write depending on whether swap is needed
@@ -249,51 +1745,392 @@ uint read_life(uint index) {
     }
 }
 
-// https://en.wikipedia.org/wiki/Conway%27s_Game_of_Life
+// Read the state written by the previous life step
+uint read_next_life(uint index) {
+    if (push_constants.swap_read_order) {
+        return life_in[index];
+    } else {
+        return life_out[index];
+    }
+}
+
+// Next is true to read the state written by the previous life step
+uint read_neighbour(ivec2 pos, bool next) {
+    if (!resolve_pos(pos)) {
+        return 0u;
+    }
+    return next ? read_next_life(get_index(pos)) : read_life(get_index(pos));
+}
+
+// Birth & survival conditions are either masks (B/S rules) or ranges (Larger than Life)
+bool rule_contains(uint mask, uint range_min, uint range_max, int alive_count) {
+    uint count = uint(alive_count);
+    return (count < 32 && (mask & (1u << count)) != 0)
+        || (count >= range_min && count <= range_max);
+}
+
+// Neighbourhood of the work group is loaded to shared memory once, so that each invocation
+// doesn't need to read (2r + 1)^2 cells from the buffer.
+// Tile has room for the largest radius, see MAX_RADIUS in rule.rs
+#define LOCAL_SIZE 8
+#define MAX_RADIUS 16
+#define TILE_SIZE (LOCAL_SIZE + 2 * MAX_RADIUS)
+shared uint tile[TILE_SIZE * TILE_SIZE];
+
+void load_tile(bool next) {
+    int radius = int(push_constants.radius);
+    int tile_size = LOCAL_SIZE + 2 * radius;
+    ivec2 tile_origin = ivec2(gl_WorkGroupID.xy) * LOCAL_SIZE - radius;
+    for (int i = int(gl_LocalInvocationIndex); i < tile_size * tile_size; i += LOCAL_SIZE * LOCAL_SIZE) {
+        ivec2 tile_pos = ivec2(i % tile_size, i / tile_size);
+        tile[tile_pos.y * TILE_SIZE + tile_pos.x] = read_neighbour(tile_origin + tile_pos, next) == 1 ? 1 : 0;
+    }
+}
+
+int count_neighbours() {
+    int radius = int(push_constants.radius);
+    ivec2 center = ivec2(gl_LocalInvocationID.xy) + radius;
+    int alive_count = 0;
+    for (int dy = -radius; dy <= radius; dy++) {
+        // Von Neumann neighbourhood is a diamond
+        int row_radius = push_constants.neighbourhood == 1 ? radius - abs(dy) : radius;
+        int row = (center.y + dy) * TILE_SIZE + center.x;
+        for (int dx = -row_radius; dx <= row_radius; dx++) {
+            alive_count += int(tile[row + dx]);
+        }
+    }
+    if (!push_constants.include_center) {
+        alive_count -= int(tile[center.y * TILE_SIZE + center.x]);
+    }
+    return alive_count;
+}
+
+// Life-like rules in B/S notation, https://conwaylife.com/wiki/Life-like_cellular_automaton
+// Generations rules, https://conwaylife.com/wiki/Generations
+// and Larger than Life rules, https://conwaylife.com/wiki/Larger_than_Life
+// 0 is dead, 1 is alive, rest are dying states. Only live cells count as neighbours.
+// Each cell only touches its own age, so ages are updated in place
+void update_age(int index, bool alive) {
+    uint age = ages[index];
+    bool was_alive = (age & AGE_ALIVE_BIT) != 0;
+    uint count = was_alive == alive ? min((age & AGE_MAX) + 1, AGE_MAX) : 0;
+    ages[index] = alive ? (count | AGE_ALIVE_BIT) : count;
+}
+
+vec4 age_color(uint state, vec4 state_color, uint age) {
+    int last_gradient = age_colors.length() - 2;
+    if (state == 1) {
+        float t = float(min(age, push_constants.max_age)) / float(max(push_constants.max_age, 1u)) * float(last_gradient);
+        int i = int(t);
+        return mix(age_colors[i], age_colors[min(i + 1, last_gradient)], fract(t));
+    } else if (age < push_constants.trail_length) {
+        // Trail fades from trail color to dead color
+        float t = float(age) / float(push_constants.trail_length);
+        return mix(age_colors[last_gradient + 1], state_color, t);
+    }
+    return state_color;
+}
+
 void compute_life() {
+    load_tile(false);
+    barrier();
+
+    ivec2 dims = ivec2(imageSize(img));
     ivec2 pos = ivec2(gl_GlobalInvocationID.xy);
+    if (pos.x >= dims.x || pos.y >= dims.y) {
+        return;
+    }
     int index = get_index(pos);
-    
-    ivec2 up_left = pos + ivec2(-1, 1);
-    ivec2 up = pos + ivec2(0, 1);
-    ivec2 up_right = pos + ivec2(1, 1);
-    ivec2 right = pos + ivec2(1, 0);
-    ivec2 down_right = pos + ivec2(1, -1);
-    ivec2 down = pos + ivec2(0, -1);
-    ivec2 down_left = pos + ivec2(-1, -1);
-    ivec2 left = pos + ivec2(-1, 0);
-
-    int alive_count = 0;
-    if (life_out[get_index(up_left)] == 1) { alive_count += 1; }
-    if (life_out[get_index(up)] == 1) { alive_count += 1; }
-    if (life_out[get_index(up_right)] == 1) { alive_count += 1; }
-    if (life_out[get_index(right)] == 1) { alive_count += 1; }
-    if (life_out[get_index(down_right)] == 1) { alive_count += 1; }
-    if (life_out[get_index(down)] == 1) { alive_count += 1; }
-    if (life_out[get_index(down_left)] == 1) { alive_count += 1; }
-    if (life_out[get_index(left)] == 1) { alive_count += 1; }
+    int alive_count = count_neighbours();
 
     uint current_life = read_life(index);
+    uint next_life;
     // Dead becomes alive
-    if (current_life == 0 && alive_count == 3) {
-        write_life(index, 1);
-    } // Becomes dead
-    else if (current_life == 1 && alive_count < 2 || alive_count > 3) {
-        write_life(index, 0);
-    } // Else Do nothing
+    if (current_life == 0 && rule_contains(push_constants.birth_mask, push_constants.birth_min, push_constants.birth_max, alive_count)) {
+        next_life = 1;
+    } // Stays dead
+    else if (current_life == 0) {
+        next_life = 0;
+    } // Stays alive
+    else if (current_life == 1 && rule_contains(push_constants.survival_mask, push_constants.survival_min, push_constants.survival_max, alive_count)) {
+        next_life = 1;
+    } // Starts or continues dying, dead after last state
     else {
-        write_life(index, current_life);
+        next_life = (current_life + 1) % push_constants.num_states;
     }
+    write_life(index, next_life);
+    update_age(index, next_life == 1);
+}
+
+// Cells in the neighbourhood which are counted
+float neighbourhood_size() {
+    int r = int(push_constants.radius);
+    int size = push_constants.neighbourhood == 1 ? 2 * r * (r + 1) + 1 : (2 * r + 1) * (2 * r + 1);
+    return float(push_constants.include_center ? size : size - 1);
+}
+
+// Sample theme gradient, t from 0 to 1
+vec4 gradient_color(float t) {
+    int last = palette_colors.length() - 1;
+    float x = clamp(t, 0.0, 1.0) * float(last - PALETTE_GRADIENT);
+    int i = PALETTE_GRADIENT + int(x);
+    return mix(palette_colors[i], palette_colors[min(i + 1, last)], fract(x));
 }
 
 void compute_color() {
+    // Neighbours of the colored step. Color mode is uniform, so the barrier is fine
+    if (push_constants.color_mode == COLOR_NEIGHBOUR_COUNT
+        || (push_constants.color_mode == COLOR_LUT && push_constants.lut_input == LUT_NEIGHBOUR_COUNT)) {
+        load_tile(true);
+        barrier();
+    }
+
+    ivec2 dims = ivec2(imageSize(img));
     ivec2 pos = ivec2(gl_GlobalInvocationID.xy);
+    if (pos.x >= dims.x || pos.y >= dims.y) {
+        return;
+    }
     int index = get_index(pos);
-    if (life_out[index] == 1) {
-        imageStore(img, pos, push_constants.life_color);
+    uint state = read_next_life(index);
+    vec4 color = state_colors[min(state, uint(state_colors.length() - 1))];
+    uint mode = push_constants.color_mode;
+    // Dying states of Generations keep their state colors, except in LUT
+    if (mode == COLOR_AGE && state <= 1) {
+        color = age_color(state, color, ages[index] & AGE_MAX);
+    } else if (mode == COLOR_NEIGHBOUR_COUNT && state == 1) {
+        color = gradient_color(float(count_neighbours()) / neighbourhood_size());
+    } else if (mode == COLOR_BIRTHS_AND_SURVIVORS && state == 1) {
+        // Previous step is still in the other buffer
+        color = palette_colors[read_life(index) == 1 ? PALETTE_SURVIVOR : PALETTE_BIRTH];
+    } else if (mode == COLOR_LUT) {
+        float t = push_constants.lut_input == LUT_NEIGHBOUR_COUNT
+            ? float(count_neighbours()) / neighbourhood_size()
+            : float(state) / float(max(push_constants.num_states - 1, 1u));
+        // Map to texel centers, so that 0 & 1 sample the first & last texel exactly
+        float texels = float(textureSize(lut, 0));
+        color = texture(lut, (0.5 + t * (texels - 1.0)) / texels);
+    }
+    imageStore(img, pos, color);
+}
+
+void main() {
+    // Step is uniform across the dispatch, so barriers within compute_life are fine
+    if (push_constants.step == 0) {
+        compute_life();
+    } else {
+        compute_color();
+    }
+}"
+    }
+}
+
+mod compute_packed_life_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform writeonly image2D img;
+// Bit b of word i holds cell (i % words_per_row) * 32 + b of row i / words_per_row
+layout(set = 0, binding = 1) buffer LifeInBuffer { uint life_in[]; };
+layout(set = 0, binding = 2) buffer LifeOutBuffer { uint life_out[]; };
+layout(set = 0, binding = 3) readonly buffer StateColorBuffer { vec4 state_colors[]; };
+
+layout(push_constant) uniform PushConstants {
+    int step;
+    bool swap_read_order;
+    // Bit n is set if n live neighbours cause birth / survival
+    uint birth_mask;
+    uint survival_mask;
+    // See BoundaryMode
+    uint boundary_mode;
+    // Grid size in cells
+    uint width;
+    uint height;
+    uint words_per_row;
+    // Cells per color image pixel along each axis, power of two
+    uint image_scale;
+} push_constants;
+
+#define BOUNDARY_DEAD 0
+#define BOUNDARY_TORUS 1
+#define BOUNDARY_MIRROR 2
+#define BOUNDARY_KLEIN_BOTTLE 3
+#define BOUNDARY_PROJECTIVE_PLANE 4
+
+ivec2 grid_dims() {
+    return ivec2(push_constants.width, push_constants.height);
+}
+
+// Integer % is undefined for negative operands in GLSL, thus floor division
+ivec2 floor_div(ivec2 a, ivec2 b) {
+    return ivec2(floor(vec2(a) / vec2(b)));
+}
+
+int reflect_coord(int v, int len) {
+    int m = v - 2 * len * floor_div(ivec2(v), ivec2(2 * len)).x;
+    return m >= len ? 2 * len - 1 - m : m;
+}
+
+// Same as in the unpacked life shader. Must match BoundaryMode::resolve
+bool resolve_pos(inout ivec2 pos) {
+    ivec2 dims = grid_dims();
+    if (pos.x >= 0 && pos.x < dims.x && pos.y >= 0 && pos.y < dims.y) {
+        return true;
+    }
+    if (push_constants.boundary_mode == BOUNDARY_DEAD) {
+        return false;
+    }
+    if (push_constants.boundary_mode == BOUNDARY_MIRROR) {
+        pos = ivec2(reflect_coord(pos.x, dims.x), reflect_coord(pos.y, dims.y));
+        return true;
+    }
+    ivec2 wraps = floor_div(pos, dims);
+    ivec2 wrapped = pos - wraps * dims;
+    if (push_constants.boundary_mode != BOUNDARY_TORUS && (wraps.y & 1) != 0) {
+        wrapped.x = dims.x - 1 - wrapped.x;
+    }
+    if (push_constants.boundary_mode == BOUNDARY_PROJECTIVE_PLANE && (wraps.x & 1) != 0) {
+        wrapped.y = dims.y - 1 - wrapped.y;
+    }
+    pos = wrapped;
+    return true;
+}
+
+// See the unpacked life shader on why buffers aren't swapped
+void write_word(uint index, uint word) {
+    if (push_constants.swap_read_order) {
+        life_in[index] = word;
+    } else {
+        life_out[index] = word;
+    }
+}
+
+uint read_word(uint index) {
+    if (push_constants.swap_read_order) {
+        return life_out[index];
+    } else {
+        return life_in[index];
+    }
+}
+
+// Read the word written by the previous life step
+uint read_next_word(uint index) {
+    if (push_constants.swap_read_order) {
+        return life_in[index];
     } else {
-        imageStore(img, pos, push_constants.dead_color);
+        return life_out[index];
+    }
+}
+
+uint read_cell(ivec2 pos) {
+    if (!resolve_pos(pos)) {
+        return 0u;
+    }
+    uint word = read_word(uint(pos.y) * push_constants.words_per_row + uint(pos.x) / 32);
+    return (word >> (uint(pos.x) % 32)) & 1;
+}
+
+// 32 consecutive cells of row y starting at x, bit b being cell x + b
+uint gather_word(int y, int x) {
+    ivec2 dims = grid_dims();
+    // Fast path, whole range within the grid: combine two words
+    if (y >= 0 && y < dims.y && x >= 0 && x + 31 < dims.x) {
+        uint index = uint(y) * push_constants.words_per_row + uint(x) / 32;
+        uint shift = uint(x) % 32;
+        if (shift == 0) {
+            return read_word(index);
+        }
+        return (read_word(index) >> shift) | (read_word(index + 1) << (32 - shift));
+    }
+    // Range crosses an edge, resolve each cell by the boundary mode
+    uint word = 0;
+    for (int b = 0; b < 32; b++) {
+        word |= read_cell(ivec2(x + b, y)) << b;
+    }
+    return word;
+}
+
+// Bit sliced addition of one neighbour word to per lane counters s0..s3 (4 bit count per lane)
+void add_neighbours(uint neighbours, inout uint s0, inout uint s1, inout uint s2, inout uint s3) {
+    uint c0 = s0 & neighbours;
+    s0 ^= neighbours;
+    uint c1 = s1 & c0;
+    s1 ^= c0;
+    uint c2 = s2 & c1;
+    s2 ^= c1;
+    s3 |= c2;
+}
+
+// Life-like rules in B/S notation for 32 cells at a time
+void compute_life() {
+    ivec2 word_pos = ivec2(gl_GlobalInvocationID.xy);
+    if (word_pos.x >= int(push_constants.words_per_row) || word_pos.y >= int(push_constants.height)) {
+        return;
+    }
+    int x = word_pos.x * 32;
+    int y = word_pos.y;
+    uint index = uint(y) * push_constants.words_per_row + uint(word_pos.x);
+
+    uint s0 = 0;
+    uint s1 = 0;
+    uint s2 = 0;
+    uint s3 = 0;
+    for (int dy = -1; dy <= 1; dy++) {
+        add_neighbours(gather_word(y + dy, x - 1), s0, s1, s2, s3);
+        add_neighbours(gather_word(y + dy, x + 1), s0, s1, s2, s3);
+        if (dy != 0) {
+            add_neighbours(gather_word(y + dy, x), s0, s1, s2, s3);
+        }
+    }
+
+    // Lanes whose neighbour count is in birth / survival conditions
+    uint born = 0;
+    uint survives = 0;
+    for (uint n = 0; n <= 8; n++) {
+        uint lanes = ((n & 1) != 0 ? s0 : ~s0)
+            & ((n & 2) != 0 ? s1 : ~s1)
+            & ((n & 4) != 0 ? s2 : ~s2)
+            & ((n & 8) != 0 ? s3 : ~s3);
+        if ((push_constants.birth_mask & (1u << n)) != 0) {
+            born |= lanes;
+        }
+        if ((push_constants.survival_mask & (1u << n)) != 0) {
+            survives |= lanes;
+        }
+    }
+
+    uint current = read_word(index);
+    uint next = (current & survives) | (~current & born);
+    // Bits past the row width are padding and stay dead
+    int valid_bits = int(push_constants.width) - x;
+    if (valid_bits < 32) {
+        next &= (1u << uint(valid_bits)) - 1;
+    }
+    write_word(index, next);
+}
+
+// Each pixel shows the density of live cells in its image_scale x image_scale block
+void compute_color() {
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 img_dims = ivec2(imageSize(img));
+    if (pos.x >= img_dims.x || pos.y >= img_dims.y) {
+        return;
     }
+    uint scale = push_constants.image_scale;
+    uvec2 origin = uvec2(pos) * scale;
+    uint block_mask = scale == 32 ? 0xffffffffu : (1u << scale) - 1;
+    uint alive = 0;
+    uint cells = 0;
+    for (uint row = origin.y; row < min(origin.y + scale, push_constants.height); row++) {
+        uint word = read_next_word(row * push_constants.words_per_row + origin.x / 32);
+        alive += bitCount((word >> (origin.x % 32)) & block_mask);
+        cells += min(scale, push_constants.width - origin.x);
+    }
+    float density = float(alive) / float(max(cells, 1u));
+    imageStore(img, pos, mix(state_colors[0], state_colors[1], density));
 }
 
 void main() {
@@ -305,3 +2142,72 @@ void main() {
 }"
     }
 }
+
+mod compute_stats_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+#version 450
+
+layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+layout(set = 0, binding = 0) readonly buffer LifeInBuffer { uint life_in[]; };
+layout(set = 0, binding = 1) readonly buffer LifeOutBuffer { uint life_out[]; };
+// Alive, births & deaths per step
+layout(set = 0, binding = 2) buffer StatsBuffer { uint stats[]; };
+
+layout(push_constant) uniform PushConstants {
+    // Whether the step wrote to life_in
+    bool current_is_in;
+    // 32 cells per word, padding bits are always dead
+    bool packed;
+    // Words in a grid buffer
+    uint len;
+    uint slot;
+} push_constants;
+
+shared uvec3 partial_counts[256];
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    uint local_index = gl_LocalInvocationIndex;
+
+    uvec3 counts = uvec3(0);
+    if (index < push_constants.len) {
+        uint current = push_constants.current_is_in ? life_in[index] : life_out[index];
+        uint previous = push_constants.current_is_in ? life_out[index] : life_in[index];
+        if (push_constants.packed) {
+            counts = uvec3(
+                bitCount(current),
+                bitCount(current & ~previous),
+                bitCount(previous & ~current)
+            );
+        } else {
+            // Dying states of Generations rules are neither alive nor born
+            counts = uvec3(
+                uint(current == 1),
+                uint(previous == 0 && current == 1),
+                uint(previous == 1 && current != 1)
+            );
+        }
+    }
+
+    // Tree reduction within the workgroup, then one atomic per workgroup
+    partial_counts[local_index] = counts;
+    barrier();
+    for (uint stride = 128; stride > 0; stride >>= 1) {
+        if (local_index < stride) {
+            partial_counts[local_index] += partial_counts[local_index + stride];
+        }
+        barrier();
+    }
+    if (local_index == 0) {
+        uint base = push_constants.slot * 3;
+        atomicAdd(stats[base], partial_counts[0].x);
+        atomicAdd(stats[base + 1], partial_counts[0].y);
+        atomicAdd(stats[base + 2], partial_counts[0].z);
+    }
+}
+"
+    }
+}
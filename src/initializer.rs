@@ -0,0 +1,422 @@
+use std::{fs::File, io::Read, path::Path};
+
+use bevy::math::IVec2;
+use rand::Rng;
+
+use crate::edit::cell_random;
+use crate::simulation::{LifeRng, DEFAULT_DENSITY};
+
+/// Symmetry of a symmetric soup
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    /// Same after rotating 180 degrees
+    C2,
+    /// Same after rotating 90 degrees
+    C4,
+    /// Same after rotating 90 degrees or mirroring
+    D8,
+}
+
+/// Starting grid of a simulation, used when building or resetting it, e.g. with
+/// `GameOfLife::with_initializer` & `GameOfLife::reset`. Random initializers draw from the
+/// simulation's seeded RNG, so the same seed gives the same grid.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GridInitializer {
+    /// All cells dead
+    Empty,
+    /// Each cell alive with probability `density`
+    Soup { density: f32 },
+    /// Soup which is symmetric around the grid center. C4 & D8 need a square, so they fill the
+    /// largest centered square and leave the rest dead.
+    SymmetricSoup { density: f32, symmetry: Symmetry },
+    /// Blobs of value noise. Noise features are `scale` cells wide, with each further octave
+    /// adding half as large details. Cells where the noise, from 0 to 1, is above `threshold`
+    /// are alive.
+    Noise {
+        scale: f32,
+        octaves: u32,
+        threshold: f32,
+    },
+    /// Soup in a centered square of `size` cells, rest dead
+    CenteredSquare { size: u32, density: f32 },
+    /// Greyscale image stretched over the grid. Pixels brighter than `threshold` are alive.
+    Image {
+        /// Image size in pixels
+        size: [u32; 2],
+        /// One value per pixel, row by row from the top
+        luma: Vec<u8>,
+        threshold: u8,
+    },
+}
+
+impl Default for GridInitializer {
+    fn default() -> Self {
+        GridInitializer::Soup {
+            density: DEFAULT_DENSITY,
+        }
+    }
+}
+
+impl GridInitializer {
+    /// Image initializer from a PNG. Colors are converted to luma, alpha is ignored.
+    pub fn from_png<P: AsRef<Path>>(
+        path: P,
+        threshold: u8,
+    ) -> Result<GridInitializer, png::DecodingError> {
+        GridInitializer::read_png(File::open(path)?, threshold)
+    }
+
+    fn read_png<R: Read>(reader: R, threshold: u8) -> Result<GridInitializer, png::DecodingError> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels)?;
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb | png::ColorType::Indexed => 3,
+            png::ColorType::Rgba => 4,
+        };
+        let mut luma = Vec::with_capacity((info.width * info.height) as usize);
+        for row in pixels
+            .chunks_exact(info.line_size)
+            .take(info.height as usize)
+        {
+            for pixel in row.chunks_exact(channels).take(info.width as usize) {
+                luma.push(match channels {
+                    1 | 2 => pixel[0],
+                    _ => (0.299 * pixel[0] as f32
+                        + 0.587 * pixel[1] as f32
+                        + 0.114 * pixel[2] as f32)
+                        .round() as u8,
+                });
+            }
+        }
+        Ok(GridInitializer::Image {
+            size: [info.width, info.height],
+            luma,
+            threshold,
+        })
+    }
+
+    /// Cell states of `cells` handed out a row at a time, with the same random numbers. Empty
+    /// grids, soups, noise & images are generated row by row, so that large packed grids never
+    /// hold a state per cell.
+    pub fn for_each_row<F>(&self, size: [u32; 2], rng: &mut LifeRng, mut f: F)
+    where
+        F: FnMut(&[u32]),
+    {
+        let mut row = vec![0; size[0] as usize];
+        match self {
+            GridInitializer::Empty => {
+                for _ in 0..size[1] {
+                    f(&row);
+                }
+            }
+            GridInitializer::Soup { density } => {
+                for _ in 0..size[1] {
+                    f(&soup([size[0], 1], *density, rng));
+                }
+            }
+            GridInitializer::Noise {
+                scale,
+                octaves,
+                threshold,
+            } => {
+                let noise = ValueNoise::new(*scale, *octaves, rng);
+                for y in 0..size[1] {
+                    for (x, cell) in row.iter_mut().enumerate() {
+                        *cell = (noise.value(x as u32, y) > *threshold) as u32;
+                    }
+                    f(&row);
+                }
+            }
+            GridInitializer::Image {
+                size: image_size,
+                luma,
+                threshold,
+            } => {
+                assert_eq!(
+                    luma.len(),
+                    image_size[0] as usize * image_size[1] as usize,
+                    "Image must have a value for each pixel"
+                );
+                // In u64, so that large grids & images don't overflow
+                let scaled =
+                    |v: u32, from: u32, to: u32| (v as u64 * to as u64 / from as u64) as usize;
+                for y in 0..size[1] {
+                    // Image rows go downwards, grid rows upwards
+                    let image_y = scaled(size[1] - 1 - y, size[1], image_size[1]);
+                    let image_row = &luma[image_y * image_size[0] as usize..];
+                    for (x, cell) in row.iter_mut().enumerate() {
+                        let value = image_row[scaled(x as u32, size[0], image_size[0])];
+                        *cell = (value > *threshold) as u32;
+                    }
+                    f(&row);
+                }
+            }
+            GridInitializer::SymmetricSoup { .. } | GridInitializer::CenteredSquare { .. } => self
+                .cells(size, rng)
+                .chunks_exact(size[0] as usize)
+                .for_each(f),
+        }
+    }
+
+    /// Cell states of a grid of size, row by row
+    pub fn cells(&self, size: [u32; 2], rng: &mut LifeRng) -> Vec<u32> {
+        match self {
+            GridInitializer::SymmetricSoup { density, symmetry } => {
+                symmetric_soup(size, *density, *symmetry, rng)
+            }
+            GridInitializer::CenteredSquare {
+                size: square,
+                density,
+            } => {
+                let square = [(*square).min(size[0]), (*square).min(size[1])];
+                let cells = soup(square, *density, rng);
+                centered(size, square, &cells)
+            }
+            _ => {
+                let mut cells = Vec::with_capacity((size[0] * size[1]) as usize);
+                self.for_each_row(size, rng, |row| cells.extend_from_slice(row));
+                cells
+            }
+        }
+    }
+}
+
+fn soup(size: [u32; 2], density: f32, rng: &mut LifeRng) -> Vec<u32> {
+    assert!(
+        (0.0..=1.0).contains(&density),
+        "Density must be between 0 and 1"
+    );
+    (0..size[0] * size[1])
+        .map(|_| rng.gen_bool(density as f64) as u32)
+        .collect()
+}
+
+fn symmetric_soup(size: [u32; 2], density: f32, symmetry: Symmetry, rng: &mut LifeRng) -> Vec<u32> {
+    let region = match symmetry {
+        Symmetry::C2 => size,
+        Symmetry::C4 | Symmetry::D8 => [size[0].min(size[1]); 2],
+    };
+    let random = soup(region, density, rng);
+    let [w, h] = [region[0] as i32, region[1] as i32];
+    let index = |pos: IVec2| (pos.y * w + pos.x) as usize;
+    let mut cells = vec![0; random.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let pos = IVec2::new(x, y);
+            let rotate_180 = IVec2::new(w - 1 - x, h - 1 - y);
+            let mut orbit = vec![pos, rotate_180];
+            if symmetry != Symmetry::C2 {
+                // Region is square
+                orbit.push(IVec2::new(w - 1 - y, x));
+                orbit.push(IVec2::new(y, w - 1 - x));
+            }
+            if symmetry == Symmetry::D8 {
+                let mirrored = orbit
+                    .iter()
+                    .map(|p| IVec2::new(w - 1 - p.x, p.y))
+                    .collect::<Vec<IVec2>>();
+                orbit.extend(mirrored);
+            }
+            // Every cell of an orbit takes the value of the same representative
+            let representative = orbit.into_iter().map(index).min().unwrap();
+            cells[index(pos)] = random[representative];
+        }
+    }
+    centered(size, region, &cells)
+}
+
+/// Place cells of a region at the center of an otherwise dead grid
+fn centered(size: [u32; 2], region: [u32; 2], cells: &[u32]) -> Vec<u32> {
+    let offset = [(size[0] - region[0]) / 2, (size[1] - region[1]) / 2];
+    let mut grid = vec![0; (size[0] * size[1]) as usize];
+    for y in 0..region[1] {
+        let row = ((y + offset[1]) * size[0] + offset[0]) as usize;
+        let region_row = (y * region[0]) as usize;
+        grid[row..row + region[0] as usize]
+            .copy_from_slice(&cells[region_row..region_row + region[0] as usize]);
+    }
+    grid
+}
+
+/// Fractal value noise from 0 to 1, see `GridInitializer::Noise`. Lattice values are hashed
+/// from a seed per octave, so that any cell can be computed on its own.
+struct ValueNoise {
+    scale: f32,
+    seeds: Vec<u32>,
+}
+
+impl ValueNoise {
+    fn new(scale: f32, octaves: u32, rng: &mut LifeRng) -> ValueNoise {
+        assert!(scale >= 1.0, "Noise scale must be at least 1 cell");
+        assert!(octaves > 0, "Noise needs at least one octave");
+        ValueNoise {
+            scale,
+            seeds: (0..octaves).map(|_| rng.gen()).collect(),
+        }
+    }
+
+    fn value(&self, x: u32, y: u32) -> f32 {
+        let mut scale = self.scale;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut value = 0.0;
+        for seed in self.seeds.iter() {
+            // Random values at lattice points every `scale` cells, interpolated in between
+            let (lx, ly) = (x as f32 / scale, y as f32 / scale);
+            let (x0, tx) = (lx.floor() as i32, smoothstep(lx.fract()));
+            let (y0, ty) = (ly.floor() as i32, smoothstep(ly.fract()));
+            let at = |x: i32, y: i32| cell_random(*seed, IVec2::new(x, y));
+            let bottom = lerp(at(x0, y0), at(x0 + 1, y0), tx);
+            let top = lerp(at(x0, y0 + 1), at(x0 + 1, y0 + 1), tx);
+            value += amplitude * lerp(bottom, top, ty);
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            scale = (scale * 0.5).max(1.0);
+        }
+        value / total_amplitude
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn rows_match_cells() {
+        let size = [37, 5];
+        let initializers = [
+            GridInitializer::Empty,
+            GridInitializer::default(),
+            GridInitializer::CenteredSquare {
+                size: 4,
+                density: 0.5,
+            },
+            GridInitializer::Noise {
+                scale: 4.0,
+                octaves: 3,
+                threshold: 0.5,
+            },
+            GridInitializer::Image {
+                size: [3, 2],
+                luma: vec![0, 100, 200, 250, 150, 50],
+                threshold: 120,
+            },
+        ];
+        for initializer in initializers.iter() {
+            let cells = initializer.cells(size, &mut LifeRng::seed_from_u64(7));
+            let mut rows = vec![];
+            initializer.for_each_row(size, &mut LifeRng::seed_from_u64(7), |row| {
+                assert_eq!(row.len(), size[0] as usize);
+                rows.extend_from_slice(row);
+            });
+            assert_eq!(rows, cells, "{:?}", initializer);
+        }
+    }
+
+    #[test]
+    fn symmetric_soups_are_symmetric() {
+        let size = [9, 7];
+        let at = |cells: &[u32], x: i32, y: i32| cells[(y * size[0] as i32 + x) as usize];
+        for symmetry in [Symmetry::C2, Symmetry::C4, Symmetry::D8] {
+            let initializer = GridInitializer::SymmetricSoup {
+                density: 0.5,
+                symmetry,
+            };
+            let cells = initializer.cells(size, &mut LifeRng::seed_from_u64(3));
+            assert!(cells.contains(&1) && cells.contains(&0), "{:?}", symmetry);
+            // C4 & D8 fill the centered 7x7 square, from x = 1
+            let (x0, w) = match symmetry {
+                Symmetry::C2 => (0, 9),
+                _ => (1, 7),
+            };
+            let h = 7;
+            for y in 0..h {
+                for x in 0..w {
+                    let cell = at(&cells, x0 + x, y);
+                    assert_eq!(
+                        cell,
+                        at(&cells, x0 + w - 1 - x, h - 1 - y),
+                        "{:?}",
+                        symmetry
+                    );
+                    if symmetry != Symmetry::C2 {
+                        assert_eq!(cell, at(&cells, x0 + w - 1 - y, x), "{:?}", symmetry);
+                    }
+                    if symmetry == Symmetry::D8 {
+                        assert_eq!(cell, at(&cells, x0 + w - 1 - x, y), "{:?}", symmetry);
+                    }
+                }
+            }
+            if symmetry != Symmetry::C2 {
+                for y in 0..h {
+                    assert_eq!(at(&cells, 0, y), 0);
+                    assert_eq!(at(&cells, 8, y), 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_follows_threshold() {
+        let size = [40, 30];
+        let noise = |threshold| {
+            GridInitializer::Noise {
+                scale: 8.0,
+                octaves: 2,
+                threshold,
+            }
+            .cells(size, &mut LifeRng::seed_from_u64(5))
+        };
+        let half = noise(0.5);
+        assert!(half.contains(&1) && half.contains(&0));
+        assert_eq!(half, noise(0.5));
+        assert!(noise(1.0).iter().all(|cell| *cell == 0));
+        assert!(noise(-0.1).iter().all(|cell| *cell == 1));
+        // Cells alive at a higher threshold are alive at a lower one
+        let high = noise(0.7);
+        assert!(high.iter().zip(half.iter()).all(|(h, l)| h <= l));
+    }
+
+    #[test]
+    fn png_rows_are_flipped() {
+        // 2x2 RGB image, top row white & red, bottom row black & grey
+        let pixels = [255, 255, 255, 255, 0, 0, 0, 0, 0, 128, 128, 128];
+        let mut png = vec![];
+        let mut encoder = png::Encoder::new(&mut png, 2, 2);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+
+        let initializer = GridInitializer::read_png(&png[..], 100).unwrap();
+        assert_eq!(
+            initializer,
+            GridInitializer::Image {
+                size: [2, 2],
+                luma: vec![255, 76, 0, 128],
+                threshold: 100,
+            }
+        );
+        // Grid row 0 is the bottom row of the image
+        let cells = initializer.cells([2, 2], &mut LifeRng::seed_from_u64(0));
+        assert_eq!(cells, vec![0, 1, 1, 0]);
+        // Stretched over a larger grid
+        let cells = initializer.cells([4, 2], &mut LifeRng::seed_from_u64(0));
+        assert_eq!(cells, vec![0, 0, 1, 1, 1, 1, 0, 0]);
+    }
+}
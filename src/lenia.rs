@@ -0,0 +1,601 @@
+use std::{fmt, sync::Arc};
+
+use bevy::math::{IVec2, Vec2};
+use rand::{Rng, SeedableRng};
+use vulkano::command_buffer::PrimaryCommandBuffer;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{ImageAccess, ImageUsage, StorageImage},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    sync::GpuFuture,
+};
+use vulkano_util::renderer::DeviceImageView;
+
+use crate::boundary::BoundaryMode;
+use crate::brush::{Brush, TOGGLE_STATE};
+use crate::game_of_life::ResizeAnchor;
+use crate::simulation::{random_seed, LifeRng};
+
+/// Largest kernel radius. Must match `MAX_RADIUS` in the lenia compute shader, which sizes its
+/// shared memory tile by it.
+pub const MAX_LENIA_RADIUS: u32 = 24;
+
+/// Parameters of a Lenia world, https://en.wikipedia.org/wiki/Lenia.
+/// Defaults are the parameters Orbium lives in, but the state starts as random noise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeniaSettings {
+    /// Kernel radius in cells
+    pub radius: u32,
+    /// Peak height of each concentric kernel ring, from center outwards
+    pub rings: Vec<f32>,
+    /// Center of the growth function
+    pub mu: f32,
+    /// Width of the growth function
+    pub sigma: f32,
+    /// Time step, fraction of growth applied per step
+    pub dt: f32,
+}
+
+impl Default for LeniaSettings {
+    fn default() -> Self {
+        LeniaSettings {
+            radius: 13,
+            rings: vec![1.0],
+            mu: 0.15,
+            sigma: 0.015,
+            dt: 0.1,
+        }
+    }
+}
+
+impl LeniaSettings {
+    /// Normalized kernel weights, see `kernel_weights`. Fails for settings the world can't run
+    /// with, e.g. radius 1, whose cells all lie at the center or past the ring edges.
+    fn kernel(&self) -> Result<Vec<f32>, LeniaSettingsError> {
+        if !(1..=MAX_LENIA_RADIUS).contains(&self.radius) {
+            return Err(LeniaSettingsError::InvalidRadius(self.radius));
+        }
+        if self.rings.is_empty() {
+            return Err(LeniaSettingsError::NoRings);
+        }
+        kernel_weights(self).ok_or(LeniaSettingsError::EmptyKernel)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LeniaSettingsError {
+    InvalidRadius(u32),
+    NoRings,
+    /// Kernel weights sum to zero, so they can't be normalized
+    EmptyKernel,
+}
+
+impl fmt::Display for LeniaSettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeniaSettingsError::InvalidRadius(r) => write!(
+                f,
+                "invalid Lenia radius {}, must be between 1 and {}",
+                r, MAX_LENIA_RADIUS
+            ),
+            LeniaSettingsError::NoRings => write!(f, "Lenia kernel needs a ring"),
+            LeniaSettingsError::EmptyKernel => {
+                write!(
+                    f,
+                    "Lenia kernel weights sum to zero for its radius and rings"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LeniaSettingsError {}
+
+/// Smooth bump over 0..1, zero at both ends
+fn kernel_core(r: f32) -> f32 {
+    if r <= 0.0 || r >= 1.0 {
+        0.0
+    } else {
+        (4.0 - 1.0 / (r * (1.0 - r))).exp()
+    }
+}
+
+/// Kernel weights for each offset within `(2 * radius + 1)^2` square, normalized to sum to 1.
+/// None if they sum to zero.
+fn kernel_weights(settings: &LeniaSettings) -> Option<Vec<f32>> {
+    let radius = settings.radius as i32;
+    let num_rings = settings.rings.len() as f32;
+    let mut weights = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            let r = Vec2::new(dx as f32, dy as f32).length() / radius as f32;
+            let weight = if r < 1.0 {
+                let ring_pos = r * num_rings;
+                settings.rings[ring_pos as usize] * kernel_core(ring_pos.fract())
+            } else {
+                0.0
+            };
+            weights.push(weight);
+        }
+    }
+    let sum = weights.iter().sum::<f32>();
+    if !(sum > 0.0 && sum.is_finite()) {
+        return None;
+    }
+    weights.iter_mut().for_each(|w| *w /= sum);
+    Some(weights)
+}
+
+fn rand_state(
+    compute_queue: &Arc<Queue>,
+    size: [u32; 2],
+    rng: &mut LifeRng,
+) -> Arc<CpuAccessibleBuffer<[f32]>> {
+    let state = (0..(size[0] * size[1])).map(|_| rng.gen::<f32>()).collect();
+    state_buffer(compute_queue, state)
+}
+
+fn state_buffer(compute_queue: &Arc<Queue>, state: Vec<f32>) -> Arc<CpuAccessibleBuffer<[f32]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        false,
+        state,
+    )
+    .unwrap()
+}
+
+fn state_image(compute_queue: &Arc<Queue>, size: [u32; 2]) -> DeviceImageView {
+    StorageImage::general_purpose_image_view(
+        compute_queue.clone(),
+        size,
+        Format::R8G8B8A8_UNORM,
+        ImageUsage {
+            sampled: true,
+            storage: true,
+            transfer_src: true,
+            transfer_dst: true,
+            ..ImageUsage::none()
+        },
+    )
+    .unwrap()
+}
+
+fn kernel_buffer(compute_queue: &Arc<Queue>, weights: Vec<f32>) -> Arc<CpuAccessibleBuffer<[f32]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::all(),
+        false,
+        weights,
+    )
+    .unwrap()
+}
+
+/// Distance from p to the segment from a to b
+fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let len_sq = ab.dot(ab);
+    let t = if len_sq > 0.0 {
+        ((p - a).dot(ab) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + t * ab)
+}
+
+/// Continuous cellular automaton with f32 state in `0..=1` per cell. Like `GameOfLife`, state is
+/// double buffered and colored into an image which can be drawn with `FillScreenRenderPass`.
+/// Each step the state is convolved with a ring shaped kernel, and a growth function of the
+/// result is added to the state. Edges wrap around.
+pub struct Lenia {
+    compute_queue: Arc<Queue>,
+    compute_lenia_pipeline: Arc<ComputePipeline>,
+    state_in: Arc<CpuAccessibleBuffer<[f32]>>,
+    state_out: Arc<CpuAccessibleBuffer<[f32]>>,
+    kernel: Arc<CpuAccessibleBuffer<[f32]>>,
+    image: DeviceImageView,
+    sim_steps: u32,
+    settings: LeniaSettings,
+    // Generates the initial state, then random brushes
+    rng: LifeRng,
+}
+
+impl Lenia {
+    /// Create with random noise from a random seed
+    pub fn new(
+        compute_queue: Arc<Queue>,
+        size: [u32; 2],
+        settings: LeniaSettings,
+    ) -> Result<Lenia, LeniaSettingsError> {
+        Lenia::with_seed(compute_queue, size, settings, random_seed())
+    }
+
+    /// Create with random noise generated from seed, so that a run is reproducible from its seed.
+    /// Fails if the world can't run with the settings, see `LeniaSettingsError`.
+    pub fn with_seed(
+        compute_queue: Arc<Queue>,
+        size: [u32; 2],
+        settings: LeniaSettings,
+        seed: u64,
+    ) -> Result<Lenia, LeniaSettingsError> {
+        let kernel = kernel_buffer(&compute_queue, settings.kernel()?);
+        let mut rng = LifeRng::seed_from_u64(seed);
+        let state_in = rand_state(&compute_queue, size, &mut rng);
+        let state_out = rand_state(&compute_queue, size, &mut rng);
+
+        let compute_lenia_pipeline = {
+            let shader = compute_lenia_cs::load(compute_queue.device().clone()).unwrap();
+            ComputePipeline::new(
+                compute_queue.device().clone(),
+                shader.entry_point("main").unwrap(),
+                &(),
+                None,
+                |_| {},
+            )
+            .unwrap()
+        };
+
+        let image = state_image(&compute_queue, size);
+        Ok(Lenia {
+            compute_queue,
+            compute_lenia_pipeline,
+            state_in,
+            state_out,
+            kernel,
+            image,
+            sim_steps: 0,
+            settings,
+            rng,
+        })
+    }
+
+    pub fn color_image(&self) -> DeviceImageView {
+        self.image.clone()
+    }
+
+    /// World size in cells
+    pub fn size(&self) -> [u32; 2] {
+        self.image.image().dimensions().width_height()
+    }
+
+    /// Resize the world keeping its state. State is cropped or padded with empty cells around
+    /// `anchor`, as in `GameOfLife::resize`. Buffers & color image are reallocated, and the
+    /// image is colored on next `compute`.
+    pub fn resize(&mut self, size: [u32; 2], anchor: ResizeAnchor) {
+        assert!(size[0] > 0 && size[1] > 0, "Lenia size must be positive");
+        let old_size = self.size();
+        if size == old_size {
+            return;
+        }
+        // `compute` waits for the GPU, so the state isn't in use
+        let old_state = self.current_state().read().unwrap().to_vec();
+        let offset = anchor.offset(old_size, size);
+        let mut state = vec![0.0; (size[0] * size[1]) as usize];
+        for (i, value) in old_state.iter().enumerate() {
+            let pos = IVec2::new(
+                (i as u32 % old_size[0]) as i32,
+                (i as u32 / old_size[0]) as i32,
+            ) + offset;
+            if pos.x >= 0 && pos.y >= 0 && pos.x < size[0] as i32 && pos.y < size[1] as i32 {
+                state[(pos.y * size[0] as i32 + pos.x) as usize] = *value;
+            }
+        }
+        self.state_in = state_buffer(&self.compute_queue, state.clone());
+        self.state_out = state_buffer(&self.compute_queue, state);
+        self.image = state_image(&self.compute_queue, size);
+    }
+
+    /// State buffer the next step reads, which `draw_brush` paints into
+    fn current_state(&self) -> &Arc<CpuAccessibleBuffer<[f32]>> {
+        if self.sim_steps % 2 == 0 {
+            &self.state_out
+        } else {
+            &self.state_in
+        }
+    }
+
+    pub fn settings(&self) -> &LeniaSettings {
+        &self.settings
+    }
+
+    /// Change world parameters, the kernel is rebuilt if needed. Takes effect on next `compute`.
+    /// Fails and keeps the current settings if the world can't run with them.
+    pub fn set_settings(&mut self, settings: LeniaSettings) -> Result<(), LeniaSettingsError> {
+        let weights = settings.kernel()?;
+        if settings.radius != self.settings.radius || settings.rings != self.settings.rings {
+            // New buffer instead of writing, the previous one may still be in use by the GPU
+            self.kernel = kernel_buffer(&self.compute_queue, weights);
+        }
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// Draw with a brush moving from `from` to `to`, like `GameOfLife::draw_brush`. Brush
+    /// strength falls off smoothly from the stroke towards the brush edge, so painted values
+    /// blend into the existing state: drawn cells towards 1, cleared ones towards 0 and
+    /// toggled ones from `v` towards `1 - v`. Edges wrap around as in the simulation.
+    pub fn draw_brush(&mut self, brush: &Brush, from: IVec2, to: IVec2) {
+        let size = self.size();
+        let cells = brush.cells(from, to, size, BoundaryMode::Torus, &mut self.rng);
+        let mut state = self.current_state().write().unwrap();
+        let dims = Vec2::new(size[0] as f32, size[1] as f32);
+        let (a, b) = (from.as_vec2(), to.as_vec2());
+        let mid = (a + b) / 2.0;
+        for (pos, cell_state) in cells {
+            // Wrapped cells are measured from the side of the stroke they were drawn on
+            let p = pos.as_vec2();
+            let p = p - ((p - mid) / dims).round() * dims;
+            let t = (segment_distance(p, a, b) / (brush.size as f32 + 1.0)).min(1.0);
+            let strength = 1.0 - t * t * (3.0 - 2.0 * t);
+            let index = (pos.y * size[0] as i32 + pos.x) as usize;
+            let value = match cell_state {
+                TOGGLE_STATE => 1.0 - state[index],
+                cell_state => cell_state.min(1) as f32,
+            };
+            state[index] += (value - state[index]) * strength;
+        }
+    }
+
+    pub fn compute(&mut self, high_color: [f32; 4], low_color: [f32; 4]) {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.compute_queue.device().clone(),
+            self.compute_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        // First compute the next state. Swap buffers
+        self.dispatch(
+            &mut builder,
+            high_color,
+            low_color,
+            0,
+            self.sim_steps % 2 == 0,
+        );
+
+        // Then color based on the next state. Read from the buffer we just wrote to
+        self.dispatch(
+            &mut builder,
+            high_color,
+            low_color,
+            1,
+            self.sim_steps % 2 == 0,
+        );
+
+        let command_buffer = builder.build().unwrap();
+
+        let finished = command_buffer.execute(self.compute_queue.clone()).unwrap();
+        let _ = finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        self.sim_steps += 1;
+    }
+
+    /// Build the command for a dispatch.
+    fn dispatch(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        high_color: [f32; 4],
+        low_color: [f32; 4],
+        // Step determines whether we color or compute state (see branch in the shader)
+        step: i32,
+        swap_read_order: bool,
+    ) {
+        let img_dims = self.image.image().dimensions().width_height();
+        let pipeline_layout = self.compute_lenia_pipeline.layout();
+        let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
+        let set = PersistentDescriptorSet::new(
+            desc_layout.clone(),
+            [
+                WriteDescriptorSet::image_view(0, self.image.clone()),
+                WriteDescriptorSet::buffer(1, self.state_in.clone()),
+                WriteDescriptorSet::buffer(2, self.state_out.clone()),
+                WriteDescriptorSet::buffer(3, self.kernel.clone()),
+            ],
+        )
+        .unwrap();
+
+        let push_constants = compute_lenia_cs::ty::PushConstants {
+            high_color,
+            low_color,
+            step,
+            swap_read_order: swap_read_order as u32,
+            radius: self.settings.radius,
+            mu: self.settings.mu,
+            sigma: self.settings.sigma,
+            dt: self.settings.dt,
+        };
+        builder
+            .bind_pipeline_compute(self.compute_lenia_pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
+            .push_constants(pipeline_layout.clone(), 0, push_constants)
+            .dispatch([(img_dims[0] + 7) / 8, (img_dims[1] + 7) / 8, 1])
+            .unwrap();
+    }
+}
+
+mod compute_lenia_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform writeonly image2D img;
+layout(set = 0, binding = 1) buffer StateInBuffer { float state_in[]; };
+layout(set = 0, binding = 2) buffer StateOutBuffer { float state_out[]; };
+layout(set = 0, binding = 3) readonly buffer KernelBuffer { float kernel[]; };
+
+layout(push_constant) uniform PushConstants {
+    vec4 high_color;
+    vec4 low_color;
+    int step;
+    bool swap_read_order;
+    uint radius;
+    // Growth function center & width
+    float mu;
+    float sigma;
+    float dt;
+} push_constants;
+
+int get_index(ivec2 pos) {
+    ivec2 dims = ivec2(imageSize(img));
+    return pos.y * dims.x + pos.x;
+}
+
+// Edges wrap around. Integer % is undefined for negative operands in GLSL
+ivec2 wrap_pos(ivec2 pos) {
+    ivec2 dims = ivec2(imageSize(img));
+    return pos - dims * ivec2(floor(vec2(pos) / vec2(dims)));
+}
+
+// See game_of_life.rs on why buffers aren't swapped
+void write_state(uint index, float value) {
+    if (push_constants.swap_read_order) {
+        state_in[index] = value;
+    } else {
+        state_out[index] = value;
+    }
+}
+
+float read_state(uint index) {
+    if (push_constants.swap_read_order) {
+        return state_out[index];
+    } else {
+        return state_in[index];
+    }
+}
+
+float read_next_state(uint index) {
+    if (push_constants.swap_read_order) {
+        return state_in[index];
+    } else {
+        return state_out[index];
+    }
+}
+
+// Neighbourhood of the work group is loaded to shared memory once for the convolution
+#define LOCAL_SIZE 8
+#define MAX_RADIUS 24
+#define TILE_SIZE (LOCAL_SIZE + 2 * MAX_RADIUS)
+shared float tile[TILE_SIZE * TILE_SIZE];
+
+void load_tile() {
+    int radius = int(push_constants.radius);
+    int tile_size = LOCAL_SIZE + 2 * radius;
+    ivec2 tile_origin = ivec2(gl_WorkGroupID.xy) * LOCAL_SIZE - radius;
+    for (int i = int(gl_LocalInvocationIndex); i < tile_size * tile_size; i += LOCAL_SIZE * LOCAL_SIZE) {
+        ivec2 tile_pos = ivec2(i % tile_size, i / tile_size);
+        tile[tile_pos.y * TILE_SIZE + tile_pos.x] = read_state(get_index(wrap_pos(tile_origin + tile_pos)));
+    }
+}
+
+float convolve() {
+    int radius = int(push_constants.radius);
+    int kernel_size = 2 * radius + 1;
+    ivec2 center = ivec2(gl_LocalInvocationID.xy) + radius;
+    float sum = 0.0;
+    for (int dy = -radius; dy <= radius; dy++) {
+        for (int dx = -radius; dx <= radius; dx++) {
+            float weight = kernel[(dy + radius) * kernel_size + dx + radius];
+            sum += weight * tile[(center.y + dy) * TILE_SIZE + center.x + dx];
+        }
+    }
+    return sum;
+}
+
+// Gaussian bump mapped to -1..1
+float growth(float u) {
+    float d = (u - push_constants.mu) / push_constants.sigma;
+    return 2.0 * exp(-0.5 * d * d) - 1.0;
+}
+
+// https://en.wikipedia.org/wiki/Lenia
+void compute_lenia() {
+    load_tile();
+    barrier();
+
+    ivec2 dims = ivec2(imageSize(img));
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy);
+    if (pos.x >= dims.x || pos.y >= dims.y) {
+        return;
+    }
+    int index = get_index(pos);
+    float current = read_state(index);
+    float next = clamp(current + push_constants.dt * growth(convolve()), 0.0, 1.0);
+    write_state(index, next);
+}
+
+void compute_color() {
+    ivec2 dims = ivec2(imageSize(img));
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy);
+    if (pos.x >= dims.x || pos.y >= dims.y) {
+        return;
+    }
+    float value = read_next_state(get_index(pos));
+    imageStore(img, pos, mix(push_constants.low_color, push_constants.high_color, value));
+}
+
+void main() {
+    // Step is uniform across the dispatch, so barriers within compute_lenia are fine
+    if (push_constants.step == 0) {
+        compute_lenia();
+    } else {
+        compute_color();
+    }
+}"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_is_normalized() {
+        let weights = LeniaSettings::default().kernel().unwrap();
+        assert_eq!(weights.len(), 27 * 27);
+        assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!(weights.iter().all(|w| w.is_finite() && *w >= 0.0));
+    }
+
+    #[test]
+    fn radius_1_kernel_is_rejected() {
+        let settings = LeniaSettings {
+            radius: 1,
+            ..Default::default()
+        };
+        assert_eq!(settings.kernel(), Err(LeniaSettingsError::EmptyKernel));
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let settings = LeniaSettings {
+            radius: 0,
+            ..Default::default()
+        };
+        assert_eq!(settings.kernel(), Err(LeniaSettingsError::InvalidRadius(0)));
+        let settings = LeniaSettings {
+            radius: MAX_LENIA_RADIUS + 1,
+            ..Default::default()
+        };
+        assert!(settings.kernel().is_err());
+        let settings = LeniaSettings {
+            rings: vec![],
+            ..Default::default()
+        };
+        assert_eq!(settings.kernel(), Err(LeniaSettingsError::NoRings));
+        let settings = LeniaSettings {
+            rings: vec![0.0, 0.0],
+            ..Default::default()
+        };
+        assert_eq!(settings.kernel(), Err(LeniaSettingsError::EmptyKernel));
+    }
+}
@@ -1,21 +1,69 @@
-mod game_of_life;
+pub mod age;
+pub mod boundary;
+pub mod brush;
+pub mod cpu_life;
+pub mod edit;
+pub mod game_of_life;
+pub mod initializer;
+pub mod lenia;
+pub mod palette;
+pub mod pattern;
 mod quad_pipeline;
 mod render_pass;
+pub mod rule;
+pub mod save;
+pub mod screenshot;
+pub mod simulation;
+pub mod stats;
+pub mod stroke;
+pub mod undo;
 
-use crate::game_of_life::GameOfLife;
+use crate::brush::{Brush, BrushMode, BrushShape, MAX_BRUSH_SIZE};
+use crate::game_of_life::{GameOfLife, ResizeAnchor};
+use crate::initializer::GridInitializer;
+use crate::lenia::{Lenia, LeniaSettings};
+use crate::palette::LifePalette;
 use crate::render_pass::FillScreenRenderPass;
+use crate::screenshot::{copy_image_after, ImageReadback};
+use crate::stats::LifeStats;
+#[cfg(target_os = "ios")]
+use crate::stroke::TapGesture;
+use crate::stroke::{StrokeId, Strokes};
+use crate::undo::HistoryCommand;
 use bevy::input::touch::touch_screen_input_system;
 use bevy::prelude::*;
 use bevy::time::FixedTimestep;
 use bevy::window::{WindowDescriptor, WindowResized};
 use bevy_vulkano::{BevyVulkanoWindows, VulkanoWinitConfig, VulkanoWinitPlugin};
 use mobile_entry_point::mobile_entry_point;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 use vulkano::image::ImageAccess;
 
 const WIDTH: u32 = 128;
 const HEIGHT: u32 = 256;
 const CLEAR_COLOR: [f32; 4] = [0.0; 4];
 
+/// Which simulation is run and shown. Toggle with Tab
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SimulationMode {
+    Life,
+    Lenia,
+}
+
+/// Game of life steps due since last frame. They're computed as part of the frame's GPU work in
+/// `render`, so the CPU doesn't wait for the simulation
+#[derive(Debug, Default)]
+pub struct PendingLifeSteps(u32);
+
+/// Screenshots requested with P. The color image and the composited frame are copied in
+/// `render`, and saved as PNGs once the GPU has finished the copies
+#[derive(Default)]
+pub struct Screenshots {
+    requested: bool,
+    readbacks: Vec<(PathBuf, ImageReadback)>,
+}
+
 #[mobile_entry_point]
 fn main() {
     App::new()
@@ -29,9 +77,22 @@ fn main() {
         .add_plugin(bevy::input::InputPlugin)
         .add_plugin(bevy::time::TimePlugin)
         .add_plugin(VulkanoWinitPlugin)
+        .add_event::<HistoryCommand>()
         .add_startup_system(startup)
         .add_system(touch_screen_input_system)
-        .add_system(draw_life_system)
+        .add_system(toggle_simulation_mode)
+        .add_system(request_screenshot)
+        .add_system(reset_life)
+        .add_system(change_brush)
+        .add_system(cycle_palette)
+        .add_system(apply_palette.after(cycle_palette))
+        .add_system(draw_life_system.after(toggle_simulation_mode))
+        .add_system(history_shortcuts)
+        .add_system(
+            apply_history_commands
+                .after(history_shortcuts)
+                .after(draw_life_system),
+        )
         .add_system_set_to_stage(
             CoreStage::Update,
             SystemSet::new()
@@ -41,7 +102,10 @@ fn main() {
         .add_system_set_to_stage(CoreStage::PostUpdate, SystemSet::new().with_system(render))
         .add_system_set_to_stage(
             CoreStage::Last,
-            SystemSet::new().with_system(update_image_size_on_resize),
+            SystemSet::new()
+                .with_system(update_life_stats)
+                .with_system(update_image_size_on_resize.after(update_life_stats))
+                .with_system(save_screenshots),
         )
         .run();
 }
@@ -49,7 +113,17 @@ fn main() {
 fn startup(mut commands: Commands, vulkano_windows: NonSend<BevyVulkanoWindows>) {
     let primary_window = vulkano_windows.get_primary_window_renderer().unwrap();
     // Create compute pipeline to simulate game of life
-    let game_of_life = GameOfLife::new(primary_window.graphics_queue(), [WIDTH, HEIGHT]);
+    let mut game_of_life = GameOfLife::new(primary_window.graphics_queue(), [WIDTH, HEIGHT]);
+    game_of_life.set_stats_enabled(true);
+    // Reproduce a run by creating it with GameOfLife::with_seed & Lenia::with_seed
+    bevy::log::info!("Game of life seed {}", game_of_life.seed());
+    let lenia = Lenia::with_seed(
+        primary_window.graphics_queue(),
+        [WIDTH, HEIGHT],
+        LeniaSettings::default(),
+        game_of_life.seed(),
+    )
+    .unwrap();
 
     // Create our render pass
     let fill_screen = FillScreenRenderPass::new(
@@ -58,32 +132,190 @@ fn startup(mut commands: Commands, vulkano_windows: NonSend<BevyVulkanoWindows>)
     );
     // Insert resources
     commands.insert_resource(game_of_life);
+    commands.insert_resource(lenia);
+    commands.insert_resource(SimulationMode::Life);
+    commands.insert_resource(PendingLifeSteps::default());
+    commands.insert_resource(Screenshots::default());
+    commands.insert_resource(LifeStats::default());
+    commands.insert_resource(LifePalette::default());
+    commands.insert_resource(Brush::default());
     commands.insert_resource(fill_screen);
 }
 
+fn toggle_simulation_mode(keys: Res<Input<KeyCode>>, mut mode: ResMut<SimulationMode>) {
+    if keys.just_pressed(KeyCode::Tab) {
+        *mode = match *mode {
+            SimulationMode::Life => SimulationMode::Lenia,
+            SimulationMode::Lenia => SimulationMode::Life,
+        };
+    }
+}
+
+fn request_screenshot(keys: Res<Input<KeyCode>>, mut screenshots: ResMut<Screenshots>) {
+    if keys.just_pressed(KeyCode::P) {
+        screenshots.requested = true;
+    }
+}
+
+/// Start over from a new random soup with R
+fn reset_life(keys: Res<Input<KeyCode>>, mut game_of_life: ResMut<GameOfLife>) {
+    if keys.just_pressed(KeyCode::R) {
+        game_of_life.reset(&GridInitializer::default());
+    }
+}
+
+/// Cycle brush shapes with B and modes with M, resize with [ and ]
+fn change_brush(keys: Res<Input<KeyCode>>, mut brush: ResMut<Brush>) {
+    if keys.just_pressed(KeyCode::B) {
+        brush.shape = brush.shape.next();
+    }
+    if keys.just_pressed(KeyCode::M) {
+        brush.mode = brush.mode.next();
+    }
+    if keys.just_pressed(KeyCode::LBracket) {
+        brush.size = brush.size.saturating_sub(1);
+    }
+    if keys.just_pressed(KeyCode::RBracket) {
+        brush.size = (brush.size + 1).min(MAX_BRUSH_SIZE);
+    }
+}
+
+/// Cycle color modes with C and themes with T
+fn cycle_palette(keys: Res<Input<KeyCode>>, mut palette: ResMut<LifePalette>) {
+    if keys.just_pressed(KeyCode::C) {
+        palette.mode = palette.mode.next();
+    }
+    if keys.just_pressed(KeyCode::T) {
+        palette.theme = palette.theme.next();
+    }
+}
+
+/// Undo with Ctrl+Z, redo with Ctrl+Shift+Z or Ctrl+Y. Cmd works in place of Ctrl
+fn history_shortcuts(keys: Res<Input<KeyCode>>, mut history_commands: EventWriter<HistoryCommand>) {
+    let command_key = keys.any_pressed([
+        KeyCode::LControl,
+        KeyCode::RControl,
+        KeyCode::LWin,
+        KeyCode::RWin,
+    ]);
+    if !command_key {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    if keys.just_pressed(KeyCode::Z) {
+        history_commands.send(if shift {
+            HistoryCommand::Redo
+        } else {
+            HistoryCommand::Undo
+        });
+    }
+    if keys.just_pressed(KeyCode::Y) {
+        history_commands.send(HistoryCommand::Redo);
+    }
+}
+
+/// Undo & redo edits of the game of life
+fn apply_history_commands(
+    mut history_commands: EventReader<HistoryCommand>,
+    mut game_of_life: ResMut<GameOfLife>,
+    mode: Res<SimulationMode>,
+) {
+    for command in history_commands.iter() {
+        if *mode != SimulationMode::Life {
+            continue;
+        }
+        match command {
+            HistoryCommand::Undo => game_of_life.undo(),
+            HistoryCommand::Redo => game_of_life.redo(),
+        };
+    }
+}
+
+fn apply_palette(palette: Res<LifePalette>, mut game_of_life: ResMut<GameOfLife>) {
+    if palette.is_changed() {
+        game_of_life.set_palette(&palette);
+    }
+}
+
+/// Save screenshots whose copies have finished
+fn save_screenshots(mut screenshots: ResMut<Screenshots>) {
+    screenshots
+        .readbacks
+        .retain(|(path, readback)| match readback.save_png(path) {
+            None => true,
+            Some(Ok(())) => {
+                bevy::log::info!("Saved screenshot {}", path.display());
+                false
+            }
+            Some(Err(e)) => {
+                bevy::log::error!("Failed to save screenshot {}: {}", path.display(), e);
+                false
+            }
+        });
+}
+
+fn screenshot_path(name: &str) -> PathBuf {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    screenshot_dir().join(format!("life_{}_{}.png", time, name))
+}
+
+/// Working directory, except on iOS where it's read only and the app's documents are used
+fn screenshot_dir() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) if cfg!(target_os = "ios") => PathBuf::from(home).join("Documents"),
+        _ => PathBuf::new(),
+    }
+}
+
+/// Collect stats of generations the GPU has finished
+fn update_life_stats(mut game_of_life: ResMut<GameOfLife>, mut life_stats: ResMut<LifeStats>) {
+    for stats in game_of_life.take_stats() {
+        life_stats.push(stats);
+    }
+}
+
 // Ensure image size is good for the resolution
 fn update_image_size_on_resize(
-    mut commands: Commands,
-    vulkano_windows: NonSend<BevyVulkanoWindows>,
     mut event_reader: EventReader<WindowResized>,
+    mut game_of_life: ResMut<GameOfLife>,
+    mut lenia: ResMut<Lenia>,
 ) {
     if let Some(e) = event_reader.iter().last() {
-        let primary = vulkano_windows.get_primary_window_renderer().unwrap();
         let scale = 2;
         // Shader local sizes are 8
         let width = e.width as u32 / scale - ((e.width as u32 / scale) % 8);
         let height = e.height as u32 / scale - ((e.height as u32 / scale) % 8);
-        let game_of_life = GameOfLife::new(primary.graphics_queue(), [width, height]);
-        commands.insert_resource(game_of_life);
+        if width == 0 || height == 0 {
+            return;
+        }
+        // Keep the boards, e.g. across orientation changes
+        game_of_life.resize([width, height], ResizeAnchor::Center);
+        lenia.resize([width, height], ResizeAnchor::Center);
     }
 }
 
-/// Draw life at mouse position on the game of life canvas
+/// Draw with the active brush on the game of life canvas, or on lenia canvas. Each mouse button
+/// and touch draws its own stroke, continuous between frames. Right mouse button erases. Lines
+/// are drawn from where the stroke started once it's released, and toggling brushes only toggle
+/// once per stroke. Strokes drawn at the same time are undone together. Tapping with two fingers
+/// undoes instead of drawing.
+#[allow(clippy::too_many_arguments)]
 fn draw_life_system(
     mut game_of_life: ResMut<GameOfLife>,
+    mut lenia: ResMut<Lenia>,
+    mode: Res<SimulationMode>,
+    brush: Res<Brush>,
     windows: ResMut<Windows>,
     mouse_input: Res<Input<MouseButton>>,
+    mut strokes: Local<Strokes>,
+    mut undo_group_open: Local<bool>,
     #[cfg(target_os = "ios")] touches: Res<Touches>,
+    #[cfg(target_os = "ios")] time: Res<Time>,
+    #[cfg(target_os = "ios")] mut tap: Local<TapGesture>,
+    #[cfg(target_os = "ios")] mut history_commands: EventWriter<HistoryCommand>,
 ) {
     fn normalized_window_pos(pos: Vec2, window: &bevy::window::Window) -> Vec2 {
         let width = window.width();
@@ -93,54 +325,145 @@ fn draw_life_system(
             (pos.y / height).clamp(0.0, 1.0),
         )
     }
-    if mouse_input.pressed(MouseButton::Left) {
-        let primary = windows.get_primary().unwrap();
-        if let Some(pos) = primary.cursor_position() {
-            let normalized = normalized_window_pos(pos, &primary);
-            let image_size = game_of_life
-                .color_image()
-                .image()
-                .dimensions()
-                .width_height();
-            let draw_pos = IVec2::new(
-                (image_size[0] as f32 * normalized.x) as i32,
-                (image_size[1] as f32 * normalized.y) as i32,
-            );
-            game_of_life.draw_life(draw_pos, 6);
+    /// Segment of the stroke to draw this frame, if any
+    fn stroke_segment(
+        strokes: &mut Strokes,
+        id: StrokeId,
+        pos: Option<Vec2>,
+        pressed: bool,
+        brush: &Brush,
+    ) -> Option<(Vec2, Vec2)> {
+        if !pressed {
+            let stroke = strokes.end(id)?;
+            return match brush.shape {
+                BrushShape::Line => Some((stroke.start, pos.unwrap_or(stroke.last))),
+                _ => None,
+            };
+        }
+        let pos = pos?;
+        let started = !strokes.is_active(id);
+        let (from, to) = strokes.update(id, pos);
+        match brush.shape {
+            BrushShape::Line => None,
+            // Segments overlap, so cells would be toggled back and forth
+            _ if brush.mode == BrushMode::Toggle => started.then(|| (pos, pos)),
+            _ => Some((from, to)),
+        }
+    }
+    // Brush, start & end of each segment to draw
+    let mut segments = vec![];
+    let primary = windows.get_primary().unwrap();
+    let cursor_pos = primary
+        .cursor_position()
+        .map(|pos| normalized_window_pos(pos, primary));
+    for button in [MouseButton::Left, MouseButton::Right] {
+        let brush = match button {
+            MouseButton::Right => Brush {
+                mode: BrushMode::Clear,
+                ..*brush
+            },
+            _ => *brush,
+        };
+        let pressed = mouse_input.pressed(button);
+        let id = StrokeId::Mouse(button);
+        if let Some((from, to)) = stroke_segment(&mut strokes, id, cursor_pos, pressed, &brush) {
+            segments.push((brush, from, to));
         }
     }
     #[cfg(target_os = "ios")]
-    for touch in touches.iter() {
-        let pos = touch.position();
-        let normalized = normalized_window_pos(pos, &windows.get_primary().unwrap());
-        let image_size = game_of_life
-            .color_image()
-            .image()
-            .dimensions()
-            .width_height();
-        let draw_pos = IVec2::new(
-            (image_size[0] as f32 * normalized.x) as i32,
-            (image_size[1] as f32 * normalized.y) as i32,
-        );
-        game_of_life.draw_life(draw_pos, 6);
+    {
+        for touch in touches.iter() {
+            let pos = normalized_window_pos(touch.position(), primary);
+            let id = StrokeId::Touch(touch.id());
+            if let Some((from, to)) = stroke_segment(&mut strokes, id, Some(pos), true, &brush) {
+                segments.push((*brush, from, to));
+            }
+        }
+        for touch in touches.iter_just_released() {
+            let pos = normalized_window_pos(touch.position(), primary);
+            let id = StrokeId::Touch(touch.id());
+            if let Some((from, to)) = stroke_segment(&mut strokes, id, Some(pos), false, &brush) {
+                segments.push((*brush, from, to));
+            }
+        }
+        for touch in touches.iter_just_cancelled() {
+            strokes.end(StrokeId::Touch(touch.id()));
+        }
+    }
+    #[cfg(target_os = "ios")]
+    let two_finger_tap = tap.update(
+        time.seconds_since_startup(),
+        touches
+            .iter()
+            .map(|touch| (touch.start_position(), touch.position())),
+    ) == Some(2);
+    #[cfg(not(target_os = "ios"))]
+    let two_finger_tap = false;
+    if !*undo_group_open && (!segments.is_empty() || !strokes.is_empty()) {
+        game_of_life.begin_undo_group();
+        *undo_group_open = true;
+    }
+    // Game of life image may be smaller than its grid
+    let grid_size = match *mode {
+        SimulationMode::Life => game_of_life.size(),
+        SimulationMode::Lenia => lenia.color_image().image().dimensions().width_height(),
+    };
+    let to_grid = |normalized: Vec2| {
+        IVec2::new(
+            (grid_size[0] as f32 * normalized.x) as i32,
+            (grid_size[1] as f32 * normalized.y) as i32,
+        )
+    };
+    for (brush, from, to) in segments {
+        let (from, to) = (to_grid(from), to_grid(to));
+        match *mode {
+            SimulationMode::Life => game_of_life.draw_brush(&brush, from, to),
+            SimulationMode::Lenia => lenia.draw_brush(&brush, from, to),
+        }
+    }
+    if *undo_group_open && strokes.is_empty() {
+        if two_finger_tap {
+            // Touches of the tap drew too
+            game_of_life.discard_undo_group();
+            #[cfg(target_os = "ios")]
+            history_commands.send(HistoryCommand::Undo);
+        } else {
+            game_of_life.end_undo_group();
+        }
+        *undo_group_open = false;
     }
 }
 
-fn simulate(mut game_of_life: ResMut<GameOfLife>) {
-    game_of_life.compute([1.0, 0.0, 0.0, 1.0], [0.0; 4]);
+fn simulate(
+    mut pending_life_steps: ResMut<PendingLifeSteps>,
+    mut lenia: ResMut<Lenia>,
+    mode: Res<SimulationMode>,
+    palette: Res<LifePalette>,
+) {
+    match *mode {
+        SimulationMode::Life => pending_life_steps.0 += 1,
+        SimulationMode::Lenia => {
+            let theme = palette.theme.colors();
+            lenia.compute(theme.alive, theme.dead)
+        }
+    }
 }
 
 /// All render occurs here in one system. If you want to split systems to separate, use
 /// `PipelineSyncData` to update futures. You could have `pre_render_system` and `post_render_system` to start and finish frames
 fn render(
     mut vulkano_windows: NonSendMut<BevyVulkanoWindows>,
-    game_of_life: Res<GameOfLife>,
+    mut game_of_life: ResMut<GameOfLife>,
+    mut pending_life_steps: ResMut<PendingLifeSteps>,
+    lenia: Res<Lenia>,
+    mode: Res<SimulationMode>,
     mut fill_screen: ResMut<FillScreenRenderPass>,
+    mut screenshots: ResMut<Screenshots>,
 ) {
     let primary_window = vulkano_windows.get_primary_window_renderer_mut().unwrap();
 
     // Start frame
-    let before = match primary_window.acquire() {
+    let mut before = match primary_window.acquire() {
         Err(e) => {
             bevy::log::error!("Failed to start frame: {}", e);
             return;
@@ -148,8 +471,36 @@ fn render(
         Ok(f) => f,
     };
 
-    let color_image = game_of_life.color_image();
+    // Simulate before drawing within the same chain of GPU work. Queued edits are applied
+    // without stepping too, e.g. undo steps waiting for diffs.
+    if pending_life_steps.0 > 0 || game_of_life.has_pending_edits() {
+        before = game_of_life.compute_steps_after(before, pending_life_steps.0);
+        pending_life_steps.0 = 0;
+    }
+
+    let color_image = match *mode {
+        SimulationMode::Life => game_of_life.color_image(),
+        SimulationMode::Lenia => lenia.color_image(),
+    };
     let final_image = primary_window.swapchain_image_view();
+
+    // Copy both the simulation image and the composited frame
+    if screenshots.requested {
+        let queue = primary_window.graphics_queue();
+        let (after_copy, color) = copy_image_after(queue, color_image.image().clone(), before);
+        let frame_size = final_image.image().dimensions().width_height();
+        let (after_copy, frame) =
+            fill_screen.screenshot_after(after_copy, color_image.clone(), frame_size, CLEAR_COLOR);
+        before = after_copy;
+        screenshots
+            .readbacks
+            .push((screenshot_path("grid"), color.flip_rows()));
+        screenshots
+            .readbacks
+            .push((screenshot_path("frame"), frame));
+        screenshots.requested = false;
+    }
+
     let after_render = fill_screen.draw(before, color_image, final_image, CLEAR_COLOR);
 
     // Finish Frame
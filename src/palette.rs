@@ -0,0 +1,257 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::Queue,
+    format::Format,
+    image::{view::ImageView, ImageDimensions, ImageViewAbstract, ImmutableImage, MipmapsCount},
+    sync::GpuFuture,
+};
+
+/// What cells are colored by. Packed grids only support coloring by state.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ColorMode {
+    /// Color of each state, see `GameOfLife::set_state_colors`
+    #[default]
+    State,
+    /// Age gradient & trails, see `AgeColoring`
+    Age,
+    /// Live cells along the theme gradient by their live neighbour count
+    NeighbourCount,
+    /// Live cells which were just born in one color, survivors in another
+    BirthsAndSurvivors,
+    /// Cells sampled from the palette's LUT texture
+    Lut(LutInput),
+}
+
+impl ColorMode {
+    /// Modes in the order they're cycled through
+    pub const ALL: [ColorMode; 6] = [
+        ColorMode::State,
+        ColorMode::Age,
+        ColorMode::NeighbourCount,
+        ColorMode::BirthsAndSurvivors,
+        ColorMode::Lut(LutInput::State),
+        ColorMode::Lut(LutInput::NeighbourCount),
+    ];
+
+    /// Value of the mode in the compute shader
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            ColorMode::State => 0,
+            ColorMode::Age => 1,
+            ColorMode::NeighbourCount => 2,
+            ColorMode::BirthsAndSurvivors => 3,
+            ColorMode::Lut(_) => 4,
+        }
+    }
+
+    pub fn next(&self) -> ColorMode {
+        let i = ColorMode::ALL.iter().position(|m| m == self).unwrap();
+        ColorMode::ALL[(i + 1) % ColorMode::ALL.len()]
+    }
+}
+
+/// Value used as the LUT texture coordinate, from 0 at the first texel to 1 at the last
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LutInput {
+    /// State over the number of states. Dead cells sample the first texel
+    #[default]
+    State,
+    /// Live neighbours over the size of the neighbourhood
+    NeighbourCount,
+}
+
+impl LutInput {
+    /// Value of the input in the compute shader
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            LutInput::State => 0,
+            LutInput::NeighbourCount => 1,
+        }
+    }
+}
+
+/// Built in color themes. All but `Classic` are safe for common color vision deficiencies.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PaletteTheme {
+    /// Red cells on black
+    #[default]
+    Classic,
+    /// Perceptually uniform blue-green-yellow
+    Viridis,
+    /// Viridis variant optimized for deuteranopia & protanopia
+    Cividis,
+    /// Okabe & Ito's palette for categorical colors
+    OkabeIto,
+}
+
+/// Colors of a theme
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThemeColors {
+    pub dead: [f32; 4],
+    pub alive: [f32; 4],
+    /// Gradient from low to high, for age and neighbour counts
+    pub gradient: Vec<[f32; 4]>,
+    pub birth: [f32; 4],
+    pub survivor: [f32; 4],
+    /// Color of recently dead cells
+    pub trail: [f32; 4],
+}
+
+impl PaletteTheme {
+    /// Themes in the order they're cycled through
+    pub const ALL: [PaletteTheme; 4] = [
+        PaletteTheme::Classic,
+        PaletteTheme::Viridis,
+        PaletteTheme::Cividis,
+        PaletteTheme::OkabeIto,
+    ];
+
+    pub fn next(&self) -> PaletteTheme {
+        let i = PaletteTheme::ALL.iter().position(|t| t == self).unwrap();
+        PaletteTheme::ALL[(i + 1) % PaletteTheme::ALL.len()]
+    }
+
+    pub fn colors(&self) -> ThemeColors {
+        const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+        match self {
+            PaletteTheme::Classic => ThemeColors {
+                dead: [0.0; 4],
+                alive: [1.0, 0.0, 0.0, 1.0],
+                gradient: vec![
+                    [1.0, 1.0, 0.6, 1.0],
+                    [1.0, 0.5, 0.0, 1.0],
+                    [0.8, 0.0, 0.0, 1.0],
+                    [0.3, 0.0, 0.4, 1.0],
+                ],
+                birth: [0.2, 1.0, 0.2, 1.0],
+                survivor: [1.0, 0.0, 0.0, 1.0],
+                trail: [0.1, 0.2, 0.5, 1.0],
+            },
+            PaletteTheme::Viridis => ThemeColors {
+                dead: BLACK,
+                alive: [0.992, 0.906, 0.145, 1.0],
+                gradient: vec![
+                    [0.267, 0.005, 0.329, 1.0],
+                    [0.231, 0.322, 0.545, 1.0],
+                    [0.129, 0.569, 0.549, 1.0],
+                    [0.369, 0.788, 0.384, 1.0],
+                    [0.992, 0.906, 0.145, 1.0],
+                ],
+                birth: [0.992, 0.906, 0.145, 1.0],
+                survivor: [0.129, 0.569, 0.549, 1.0],
+                trail: [0.231, 0.322, 0.545, 1.0],
+            },
+            PaletteTheme::Cividis => ThemeColors {
+                dead: BLACK,
+                alive: [1.0, 0.918, 0.275, 1.0],
+                gradient: vec![
+                    [0.0, 0.125, 0.302, 1.0],
+                    [0.255, 0.302, 0.42, 1.0],
+                    [0.486, 0.482, 0.471, 1.0],
+                    [0.737, 0.686, 0.435, 1.0],
+                    [1.0, 0.918, 0.275, 1.0],
+                ],
+                birth: [1.0, 0.918, 0.275, 1.0],
+                survivor: [0.486, 0.482, 0.471, 1.0],
+                trail: [0.255, 0.302, 0.42, 1.0],
+            },
+            PaletteTheme::OkabeIto => ThemeColors {
+                dead: BLACK,
+                alive: [0.902, 0.624, 0.0, 1.0],
+                gradient: vec![
+                    [0.0, 0.447, 0.698, 1.0],
+                    [0.337, 0.706, 0.914, 1.0],
+                    [0.0, 0.62, 0.451, 1.0],
+                    [0.941, 0.894, 0.259, 1.0],
+                    [0.902, 0.624, 0.0, 1.0],
+                    [0.835, 0.369, 0.0, 1.0],
+                ],
+                birth: [0.902, 0.624, 0.0, 1.0],
+                survivor: [0.337, 0.706, 0.914, 1.0],
+                trail: [0.0, 0.447, 0.698, 1.0],
+            },
+        }
+    }
+}
+
+/// How the simulation is colored. Applied with `GameOfLife::set_palette`, which uploads a
+/// theme's LUT the first time the theme is used and after that only updates buffers & push
+/// constants, so palettes can be switched every frame.
+#[derive(Debug, Clone, Default)]
+pub struct LifePalette {
+    pub mode: ColorMode,
+    pub theme: PaletteTheme,
+    /// 1D texture sampled in `ColorMode::Lut`, e.g. from `lut_texture`. Theme gradient is used
+    /// if not set.
+    pub lut: Option<Arc<dyn ImageViewAbstract>>,
+}
+
+/// Create a 1D LUT texture of colors, blocking until it's uploaded
+pub fn lut_texture(queue: Arc<Queue>, colors: &[[f32; 4]]) -> Arc<dyn ImageViewAbstract> {
+    let (image, upload) = ImmutableImage::from_iter(
+        lut_texels(colors),
+        ImageDimensions::Dim1d {
+            width: colors.len() as u32,
+            array_layers: 1,
+        },
+        MipmapsCount::One,
+        Format::R8G8B8A8_UNORM,
+        queue,
+    )
+    .unwrap();
+    let _ = upload
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+    ImageView::new_default(image).unwrap()
+}
+
+/// RGBA8 texels of a LUT of colors, clamped to 0..1
+fn lut_texels(colors: &[[f32; 4]]) -> Vec<u8> {
+    assert!(!colors.is_empty(), "LUT must have at least one color");
+    colors
+        .iter()
+        .flat_map(|color| color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lut_texels_are_clamped_rgba() {
+        assert_eq!(
+            lut_texels(&[[0.0, 0.5, 1.0, 1.0], [-1.0, 2.0, 0.2, 0.0]]),
+            vec![0, 128, 255, 255, 0, 255, 51, 0]
+        );
+    }
+
+    #[test]
+    fn theme_luts_are_valid() {
+        for theme in PaletteTheme::ALL {
+            let colors = theme.colors();
+            assert!(colors.gradient.len() >= 2, "{:?}", theme);
+            assert_ne!(colors.alive, colors.dead, "{:?}", theme);
+            let texels = lut_texels(&colors.gradient);
+            assert_eq!(texels.len(), colors.gradient.len() * 4);
+            // Opaque and within 0..1, so that clamping doesn't change them
+            for color in colors.gradient.iter() {
+                assert!(color.iter().all(|c| (0.0..=1.0).contains(c)), "{:?}", theme);
+            }
+            assert!(texels.chunks_exact(4).all(|texel| texel[3] == 255));
+        }
+    }
+
+    #[test]
+    fn themes_cycle() {
+        let mut theme = PaletteTheme::default();
+        for expected in PaletteTheme::ALL.iter().cycle().skip(1).take(4) {
+            theme = theme.next();
+            assert_eq!(theme, *expected);
+        }
+        assert_eq!(theme, PaletteTheme::default());
+    }
+}
@@ -0,0 +1,706 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use bevy::math::IVec2;
+
+use crate::rule::{LifeRule, MAX_STATES};
+
+/// Live cells of a pattern, e.g. a glider or a gun, loaded from a pattern file.
+///
+/// Cells are in pattern coordinates, where `(0, 0)` is the top left corner of the pattern and
+/// y grows downwards, as in pattern files. Stamping a pattern into `GameOfLife` flips it so that
+/// it's shown upright. Patterns of Generations rules also have dying cells, which are kept with
+/// their states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    size: [u32; 2],
+    cells: Vec<IVec2>,
+    // State of each cell, 1 for live cells
+    states: Vec<u32>,
+    rule: Option<LifeRule>,
+}
+
+impl Pattern {
+    /// Create a pattern of live cells of given size. Panics if a cell is outside of it.
+    pub fn new(size: [u32; 2], cells: Vec<IVec2>) -> Pattern {
+        let cells = cells.into_iter().map(|cell| (cell, 1)).collect();
+        Pattern::with_states(size, cells)
+    }
+
+    /// Create a pattern of cells with their states. Panics if a cell is outside of the size or
+    /// dead.
+    pub fn with_states(size: [u32; 2], cells: Vec<(IVec2, u32)>) -> Pattern {
+        for (cell, state) in cells.iter() {
+            assert!(
+                cell.x >= 0 && cell.y >= 0 && cell.x < size[0] as i32 && cell.y < size[1] as i32,
+                "Pattern cell {} is outside of pattern size {:?}",
+                cell,
+                size
+            );
+            assert!(*state != 0, "Pattern cell {} is dead", cell);
+        }
+        let (cells, states) = cells.into_iter().unzip();
+        Pattern {
+            size,
+            cells,
+            states,
+            rule: None,
+        }
+    }
+
+    /// Create a pattern from a set of live cells in any coordinates. The pattern is the
+    /// minimal bounding box of the cells, with its top left corner moved to `(0, 0)`.
+    pub fn from_cells<I>(cells: I) -> Pattern
+    where
+        I: IntoIterator<Item = IVec2>,
+    {
+        Pattern::from_cell_states(cells.into_iter().map(|cell| (cell, 1)))
+    }
+
+    /// Create a pattern from cells with their states in any coordinates, see `from_cells`.
+    /// Cells given twice keep their first state.
+    pub fn from_cell_states<I>(cells: I) -> Pattern
+    where
+        I: IntoIterator<Item = (IVec2, u32)>,
+    {
+        let mut cells = cells.into_iter().collect::<Vec<(IVec2, u32)>>();
+        if cells.is_empty() {
+            return Pattern::new([0, 0], vec![]);
+        }
+        let min = cells
+            .iter()
+            .fold(IVec2::splat(i32::MAX), |min, (c, _)| min.min(*c));
+        let max = cells
+            .iter()
+            .fold(IVec2::splat(i32::MIN), |max, (c, _)| max.max(*c));
+        for (cell, _) in cells.iter_mut() {
+            *cell -= min;
+        }
+        // Stable, so that the first of duplicates is kept
+        cells.sort_by_key(|(c, _)| (c.y, c.x));
+        cells.dedup_by_key(|(c, _)| *c);
+        let size = max - min + IVec2::ONE;
+        Pattern::with_states([size.x as u32, size.y as u32], cells)
+    }
+
+    /// Parse a pattern in RLE format, e.g.
+    /// ```text
+    /// #N Glider
+    /// x = 3, y = 3, rule = B3/S23
+    /// bob$2bo$3o!
+    /// ```
+    /// Lines starting with `#` are comments. The header gives the pattern size and optionally
+    /// its rule, which is left out if it isn't recognized. Body tags are `b` (or `.`) for dead
+    /// cells, `o` for live cells and `$` for end of row, each optionally preceded by a run count.
+    /// Pattern ends at `!`. Multistate patterns have states `A`..`X` for 1 to 24 and `pA`..`yO`
+    /// for 25 to 255, which must fit the rule's states.
+    pub fn from_rle(rle: &str) -> Result<Pattern, PatternError> {
+        let mut lines = rle
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let (header_line, header) = lines
+            .next()
+            .ok_or(PatternError::MissingHeader(RLE_HEADER))?;
+        let (size, rule) = parse_rle_header(header_line, header)?;
+        if !fits_grid(size) {
+            return Err(PatternError::TooLarge(size));
+        }
+
+        let mut cells = vec![];
+        let mut pos = IVec2::ZERO;
+        let mut run_count: Option<u32> = None;
+        // Prefix of a multistate tag, `p` to `y`
+        let mut prefix: Option<char> = None;
+        'body: for (line_number, line) in lines {
+            for c in line.chars() {
+                if let Some(p) = prefix.take() {
+                    let state = match c {
+                        'A'..='X' => 24 * (p as u32 - 'p' as u32 + 1) + (c as u32 - 'A' as u32 + 1),
+                        _ => 0,
+                    };
+                    if state == 0 || state >= MAX_STATES {
+                        return Err(PatternError::InvalidCharacter(line_number, c));
+                    }
+                    let run = run_count.take().unwrap_or(1);
+                    push_run(&mut cells, &mut pos, run, state, size, rule, line_number)?;
+                    continue;
+                }
+                if let Some(digit) = c.to_digit(10) {
+                    run_count = Some(
+                        run_count
+                            .unwrap_or(0)
+                            .checked_mul(10)
+                            .and_then(|n| n.checked_add(digit))
+                            .ok_or(PatternError::InvalidRunCount(line_number))?,
+                    );
+                    continue;
+                }
+                if c.is_whitespace() {
+                    continue;
+                }
+                if ('p'..='y').contains(&c) {
+                    // Run count stays for the full tag
+                    prefix = Some(c);
+                    continue;
+                }
+                let run = run_count.take().unwrap_or(1);
+                match c {
+                    'b' | '.' => pos.x += run as i32,
+                    'o' => push_run(&mut cells, &mut pos, run, 1, size, rule, line_number)?,
+                    'A'..='X' => {
+                        let state = c as u32 - 'A' as u32 + 1;
+                        push_run(&mut cells, &mut pos, run, state, size, rule, line_number)?
+                    }
+                    '$' => {
+                        pos.x = 0;
+                        pos.y += run as i32;
+                    }
+                    '!' => break 'body,
+                    c => return Err(PatternError::InvalidCharacter(line_number, c)),
+                }
+            }
+        }
+        let mut pattern = Pattern::with_states(size, cells);
+        pattern.rule = rule;
+        Ok(pattern)
+    }
+
+    /// Pattern in RLE format, with the rule in the header if the pattern has one. Lines are
+    /// wrapped at 70 columns. Patterns with dying cells or of Generations rules are written
+    /// with multistate tags.
+    pub fn to_rle(&self) -> String {
+        let mut rle = format!("x = {}, y = {}", self.size[0], self.size[1]);
+        if let Some(rule) = self.rule {
+            rle.push_str(&format!(", rule = {}", rule));
+        }
+        rle.push('\n');
+
+        let multistate = self.rule.map_or(false, |rule| rule.states() > 2)
+            || self.states.iter().any(|state| *state > 1);
+        let dead_tag = if multistate { "." } else { "b" };
+
+        // Runs of (count, tag). Trailing dead cells of rows are left out, and consecutive row
+        // ends merged
+        let mut runs: Vec<(u32, String)> = vec![];
+        let mut push_run = |count: u32, tag: &str| match runs.last_mut() {
+            Some((last_count, last_tag)) if last_tag == tag => *last_count += count,
+            _ => runs.push((count, tag.to_string())),
+        };
+        let mut pos = IVec2::ZERO;
+        for (cell, state) in self.sorted_cells() {
+            if cell.y > pos.y {
+                push_run((cell.y - pos.y) as u32, "$");
+                pos = IVec2::new(0, cell.y);
+            }
+            if cell.x > pos.x {
+                push_run((cell.x - pos.x) as u32, dead_tag);
+            }
+            if multistate {
+                push_run(1, &state_tag(state));
+            } else {
+                push_run(1, "o");
+            }
+            pos.x = cell.x + 1;
+        }
+        push_run(1, "!");
+
+        let mut line_len = 0;
+        for (count, tag) in runs {
+            let run = match count {
+                1 => tag,
+                _ => format!("{}{}", count, tag),
+            };
+            if line_len + run.len() > RLE_LINE_LENGTH {
+                rle.push('\n');
+                line_len = 0;
+            }
+            line_len += run.len();
+            rle.push_str(&run);
+        }
+        rle.push('\n');
+        rle
+    }
+
+    /// Parse a pattern in plaintext (`.cells`) format, e.g.
+    /// ```text
+    /// !Name: Glider
+    /// .O
+    /// ..O
+    /// OOO
+    /// ```
+    /// Lines starting with `!` are comments. Each other line is a row, where `.` is a dead cell and
+    /// `O` (or `*`) a live one. Rows may be shorter than the pattern width.
+    pub fn from_plaintext(plaintext: &str) -> Result<Pattern, PatternError> {
+        let mut rows = vec![];
+        for (i, line) in plaintext.lines().enumerate() {
+            if line.starts_with('!') {
+                continue;
+            }
+            let mut row = vec![];
+            for c in line.trim_end().chars() {
+                match c {
+                    '.' => row.push(false),
+                    'O' | '*' => row.push(true),
+                    c => return Err(PatternError::InvalidCharacter(i + 1, c)),
+                }
+            }
+            rows.push(row);
+        }
+        while rows.last().map_or(false, |row| row.is_empty()) {
+            rows.pop();
+        }
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let cells = rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, alive)| **alive)
+                    .map(move |(x, _)| IVec2::new(x as i32, y as i32))
+            })
+            .collect();
+        Ok(Pattern::new([width as u32, rows.len() as u32], cells))
+    }
+
+    /// Pattern in plaintext (`.cells`) format. Trailing dead cells of rows are left out. The
+    /// format has two states only, so dying cells are written as live ones.
+    pub fn to_plaintext(&self) -> String {
+        let mut rows = vec![vec![]; self.size[1] as usize];
+        for cell in self.cells.iter() {
+            let row = &mut rows[cell.y as usize];
+            if row.len() <= cell.x as usize {
+                row.resize(cell.x as usize + 1, '.');
+            }
+            row[cell.x as usize] = 'O';
+        }
+        let mut plaintext = String::new();
+        for row in rows {
+            // Empty rows keep a dead cell, so that trailing ones aren't lost
+            if row.is_empty() {
+                plaintext.push('.');
+            }
+            plaintext.extend(row);
+            plaintext.push('\n');
+        }
+        plaintext
+    }
+
+    /// Parse a pattern in Life 1.06 format, e.g.
+    /// ```text
+    /// #Life 1.06
+    /// 0 -1
+    /// 1 0
+    /// -1 1
+    /// 0 1
+    /// 1 1
+    /// ```
+    /// Each line after the header is the `x y` coordinate of a live cell, y growing downwards.
+    /// Coordinates may be negative. Pattern is the minimal bounding box of the cells.
+    pub fn from_life_106(life_106: &str) -> Result<Pattern, PatternError> {
+        let mut lines = life_106
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        match lines.next() {
+            Some((_, header)) if header == LIFE_106_HEADER => {}
+            _ => return Err(PatternError::MissingHeader(LIFE_106_HEADER)),
+        }
+        let mut cells = vec![];
+        for (line_number, line) in lines {
+            if line.starts_with('#') {
+                continue;
+            }
+            let invalid = || PatternError::InvalidCoordinates(line_number, line.to_string());
+            let mut coordinates = line.split_whitespace().map(|c| c.parse::<i32>());
+            match (coordinates.next(), coordinates.next(), coordinates.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => cells.push(IVec2::new(x, y)),
+                _ => return Err(invalid()),
+            }
+        }
+        if let Some(first) = cells.first() {
+            let (min, max) = cells
+                .iter()
+                .fold((*first, *first), |(min, max), c| (min.min(*c), max.max(*c)));
+            // Coordinates span up to 2^32 cells, which doesn't fit in an `i32`
+            let extent = |min: i32, max: i32| (max as i64 - min as i64 + 1).min(u32::MAX as i64);
+            let size = [extent(min.x, max.x) as u32, extent(min.y, max.y) as u32];
+            if !fits_grid(size) {
+                return Err(PatternError::TooLarge(size));
+            }
+        }
+        Ok(Pattern::from_cells(cells))
+    }
+
+    /// Pattern in Life 1.06 format, coordinates relative to the top left corner. The format has
+    /// two states only, so dying cells are written as live ones.
+    pub fn to_life_106(&self) -> String {
+        let mut life_106 = format!("{}\n", LIFE_106_HEADER);
+        for cell in self.cells.iter() {
+            life_106.push_str(&format!("{} {}\n", cell.x, cell.y));
+        }
+        life_106
+    }
+
+    /// Width and height of the pattern
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Live (and dying) cells in pattern coordinates
+    pub fn cells(&self) -> &[IVec2] {
+        &self.cells
+    }
+
+    /// States of `cells` in the same order, 1 for live cells
+    pub fn states(&self) -> &[u32] {
+        &self.states
+    }
+
+    /// Cells with their states
+    pub fn cell_states(&self) -> impl Iterator<Item = (IVec2, u32)> + '_ {
+        self.cells.iter().copied().zip(self.states.iter().copied())
+    }
+
+    /// Cells with their states in row order
+    fn sorted_cells(&self) -> Vec<(IVec2, u32)> {
+        let mut cells = self.cell_states().collect::<Vec<(IVec2, u32)>>();
+        cells.sort_by_key(|(c, _)| (c.y, c.x));
+        cells
+    }
+
+    /// Live cells as a set, for lookups & set operations
+    pub fn live_cells(&self) -> HashSet<IVec2> {
+        self.cells.iter().copied().collect()
+    }
+
+    /// Rule the pattern was made for, if the file specified one
+    pub fn rule(&self) -> Option<LifeRule> {
+        self.rule
+    }
+
+    pub fn set_rule(&mut self, rule: Option<LifeRule>) {
+        self.rule = rule;
+    }
+
+    /// Live and dying cells as grid positions, when the pattern's bottom left corner is placed
+    /// at `pos`. Rows are flipped, because grid row 0 is shown at the bottom.
+    pub(crate) fn grid_cells(&self, pos: IVec2) -> Vec<(IVec2, u32)> {
+        let top = self.size[1] as i32 - 1;
+        self.cell_states()
+            .map(|(cell, state)| (pos + IVec2::new(cell.x, top - cell.y), state))
+            .collect()
+    }
+}
+
+/// Expected form of RLE header, for errors
+const RLE_HEADER: &str = "x = m, y = n, rule = abc";
+/// Longest line of RLE body
+const RLE_LINE_LENGTH: usize = 70;
+/// First line of Life 1.06 files
+const LIFE_106_HEADER: &str = "#Life 1.06";
+
+/// Whether a pattern of size fits in a grid, i.e. its number of cells fits in a `u32` and its
+/// coordinates in an `i32`
+fn fits_grid(size: [u32; 2]) -> bool {
+    size[0] <= i32::MAX as u32
+        && size[1] <= i32::MAX as u32
+        && size[0].checked_mul(size[1]).is_some()
+}
+
+/// Add `run` cells of state at pos, moving pos past them
+fn push_run(
+    cells: &mut Vec<(IVec2, u32)>,
+    pos: &mut IVec2,
+    run: u32,
+    state: u32,
+    size: [u32; 2],
+    rule: Option<LifeRule>,
+    line_number: usize,
+) -> Result<(), PatternError> {
+    if let Some(rule) = rule {
+        if state >= rule.states() {
+            return Err(PatternError::InvalidState(line_number, state));
+        }
+    }
+    for _ in 0..run {
+        if pos.x >= size[0] as i32 || pos.y >= size[1] as i32 {
+            return Err(PatternError::OutOfBounds(line_number, size));
+        }
+        cells.push((*pos, state));
+        pos.x += 1;
+    }
+    Ok(())
+}
+
+/// Multistate RLE tag of a non-dead state
+fn state_tag(state: u32) -> String {
+    let letter = (b'A' + ((state - 1) % 24) as u8) as char;
+    match (state - 1) / 24 {
+        0 => letter.to_string(),
+        prefix => format!("{}{}", (b'p' + prefix as u8 - 1) as char, letter),
+    }
+}
+
+/// Parse `x = m, y = n, rule = abc` header into pattern size and rule
+fn parse_rle_header(
+    line_number: usize,
+    header: &str,
+) -> Result<([u32; 2], Option<LifeRule>), PatternError> {
+    let mut width = None;
+    let mut height = None;
+    let mut rule = None;
+    let malformed = || PatternError::InvalidHeader(line_number, header.to_string());
+    let mut rest = header;
+    while !rest.trim().is_empty() {
+        let (key, after) = rest.split_once('=').ok_or_else(malformed)?;
+        // Rule may contain commas itself, so it takes the rest of the header
+        let (value, next) = match key.trim() {
+            "rule" => (after, ""),
+            _ => after.split_once(',').unwrap_or((after, "")),
+        };
+        let value = value.trim();
+        match key.trim() {
+            "x" => width = Some(value.parse::<u32>().map_err(|_| malformed())?),
+            "y" => height = Some(value.parse::<u32>().map_err(|_| malformed())?),
+            "rule" => {
+                // Bounded grid suffix, e.g. `B3/S23:T64,64`, isn't part of the rule. Named rules
+                // (e.g. `LifeHistory`) aren't supported, but their patterns still load
+                let value = value.split(':').next().unwrap();
+                rule = value.parse::<LifeRule>().ok();
+            }
+            _ => return Err(malformed()),
+        }
+        rest = next;
+    }
+    match (width, height) {
+        (Some(width), Some(height)) => Ok(([width, height], rule)),
+        _ => Err(malformed()),
+    }
+}
+
+/// Errors from parsing pattern files. Line numbers start from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// Expected header
+    MissingHeader(&'static str),
+    InvalidHeader(usize, String),
+    InvalidRunCount(usize),
+    /// State the pattern's rule doesn't have
+    InvalidState(usize, u32),
+    InvalidCharacter(usize, char),
+    InvalidCoordinates(usize, String),
+    OutOfBounds(usize, [u32; 2]),
+    /// Pattern size larger than any grid
+    TooLarge([u32; 2]),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::MissingHeader(header) => {
+                write!(f, "pattern has no '{}' header", header)
+            }
+            PatternError::InvalidHeader(line, header) => write!(
+                f,
+                "line {}: malformed header '{}', expected e.g. 'x = 3, y = 3, rule = B3/S23'",
+                line, header
+            ),
+            PatternError::InvalidRunCount(line) => write!(f, "line {}: run count too large", line),
+            PatternError::InvalidState(line, state) => write!(
+                f,
+                "line {}: state {} doesn't exist in the pattern's rule",
+                line, state
+            ),
+            PatternError::InvalidCharacter(line, c) => {
+                write!(f, "line {}: invalid character '{}' in pattern", line, c)
+            }
+            PatternError::InvalidCoordinates(line, coordinates) => write!(
+                f,
+                "line {}: invalid cell coordinates '{}', expected e.g. '-1 2'",
+                line, coordinates
+            ),
+            PatternError::OutOfBounds(line, size) => write!(
+                f,
+                "line {}: cells outside of pattern size {}x{}",
+                line, size[0], size[1]
+            ),
+            PatternError::TooLarge(size) => write!(
+                f,
+                "pattern size {}x{} is larger than any grid",
+                size[0], size[1]
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER_RLE: &str = "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+
+    fn glider_cells() -> Vec<IVec2> {
+        vec![
+            IVec2::new(1, 0),
+            IVec2::new(2, 1),
+            IVec2::new(0, 2),
+            IVec2::new(1, 2),
+            IVec2::new(2, 2),
+        ]
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let glider = Pattern::from_rle(GLIDER_RLE).unwrap();
+        assert_eq!(glider.size(), [3, 3]);
+        assert_eq!(glider.cells(), &glider_cells()[..]);
+        assert_eq!(glider.rule(), Some("B3/S23".parse().unwrap()));
+        assert_eq!(glider.to_rle(), GLIDER_RLE);
+
+        // Comments, wrapped lines, `.`/`A` tags & row runs
+        let rle = "#N Glider\n#C comment\nx = 3, y = 4\n.A$\n2.\nA2$3A!";
+        let glider = Pattern::from_rle(rle).unwrap();
+        assert_eq!(glider.rule(), None);
+        assert_eq!(glider.to_rle(), "x = 3, y = 4\nbo$2bo2$3o!\n");
+        assert_eq!(Pattern::from_rle(&glider.to_rle()).unwrap(), glider);
+    }
+
+    #[test]
+    fn rle_multistate() {
+        let rle = "x = 5, y = 1, rule = B2/S/C3\nA.B2A!\n";
+        let pattern = Pattern::from_rle(rle).unwrap();
+        assert_eq!(pattern.states(), &[1, 2, 1, 1]);
+        assert_eq!(pattern.to_rle(), rle);
+
+        // Prefixed states above 24
+        let rle = "x = 3, y = 2, rule = B2/S/C256\nX2pA$yO!";
+        let pattern = Pattern::from_rle(rle).unwrap();
+        assert_eq!(pattern.states(), &[24, 25, 25, 255]);
+        assert_eq!(Pattern::from_rle(&pattern.to_rle()).unwrap(), pattern);
+
+        // States the rule doesn't have
+        assert_eq!(
+            Pattern::from_rle("x = 2, y = 1, rule = B3/S23\nAB!"),
+            Err(PatternError::InvalidState(2, 2))
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 1, y = 1\nyP!"),
+            Err(PatternError::InvalidCharacter(2, 'P'))
+        );
+    }
+
+    #[test]
+    fn rle_unknown_rule_loads() {
+        let rle = "x = 3, y = 3, rule = LifeHistory\nbo$2bo$3o!";
+        let glider = Pattern::from_rle(rle).unwrap();
+        assert_eq!(glider.rule(), None);
+        assert_eq!(glider.cells(), &glider_cells()[..]);
+
+        // Bounded grid suffix
+        let rle = "x = 3, y = 3, rule = B3/S23:T64,64\nbo$2bo$3o!";
+        let glider = Pattern::from_rle(rle).unwrap();
+        assert_eq!(glider.rule(), Some("B3/S23".parse().unwrap()));
+    }
+
+    #[test]
+    fn rle_errors() {
+        assert_eq!(
+            Pattern::from_rle("# only a comment"),
+            Err(PatternError::MissingHeader(RLE_HEADER))
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 3\nbo!"),
+            Err(PatternError::InvalidHeader(1, "x = 3".to_string()))
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 2, y = 1\n3o!"),
+            Err(PatternError::OutOfBounds(2, [2, 1]))
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 2, y = 1\nbz!"),
+            Err(PatternError::InvalidCharacter(2, 'z'))
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 2, y = 1\n99999999999o!"),
+            Err(PatternError::InvalidRunCount(2))
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 100000, y = 100000\no!"),
+            Err(PatternError::TooLarge([100000, 100000]))
+        );
+    }
+
+    #[test]
+    fn plaintext_round_trip() {
+        let plaintext = "!Name: Glider\n.O\n..*\nOOO\n";
+        let glider = Pattern::from_plaintext(plaintext).unwrap();
+        assert_eq!(glider.size(), [3, 3]);
+        assert_eq!(glider.cells(), &glider_cells()[..]);
+        assert_eq!(glider.to_plaintext(), ".O\n..O\nOOO\n");
+        assert_eq!(
+            Pattern::from_plaintext(&glider.to_plaintext()).unwrap(),
+            glider
+        );
+
+        // Empty rows are kept
+        let pattern = Pattern::from_plaintext("O\n\n.O\n").unwrap();
+        assert_eq!(pattern.size(), [2, 3]);
+        assert_eq!(pattern.to_plaintext(), "O\n.\n.O\n");
+
+        assert_eq!(
+            Pattern::from_plaintext(".O\n.x\n"),
+            Err(PatternError::InvalidCharacter(2, 'x'))
+        );
+    }
+
+    #[test]
+    fn life_106_round_trip() {
+        let life_106 = "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+        let glider = Pattern::from_life_106(life_106).unwrap();
+        assert_eq!(glider.size(), [3, 3]);
+        assert_eq!(
+            glider.live_cells(),
+            glider_cells().into_iter().collect::<HashSet<IVec2>>()
+        );
+        assert_eq!(
+            Pattern::from_life_106(&glider.to_life_106()).unwrap(),
+            glider
+        );
+
+        assert_eq!(
+            Pattern::from_life_106("0 0\n"),
+            Err(PatternError::MissingHeader(LIFE_106_HEADER))
+        );
+        assert_eq!(
+            Pattern::from_life_106("#Life 1.06\n0 0\n1 x\n"),
+            Err(PatternError::InvalidCoordinates(3, "1 x".to_string()))
+        );
+        assert_eq!(
+            Pattern::from_life_106("#Life 1.06\n-2147483648 0\n2147483647 0\n"),
+            Err(PatternError::TooLarge([u32::MAX, 1]))
+        );
+    }
+
+    #[test]
+    fn grid_cells_are_live_cells_flipped() {
+        let glider = Pattern::from_rle(GLIDER_RLE).unwrap();
+        let mut cells = glider.grid_cells(IVec2::new(10, 20));
+        cells.sort_by_key(|(c, _)| (c.y, c.x));
+        assert_eq!(
+            cells,
+            vec![
+                (IVec2::new(10, 20), 1),
+                (IVec2::new(11, 20), 1),
+                (IVec2::new(12, 20), 1),
+                (IVec2::new(12, 21), 1),
+                (IVec2::new(11, 22), 1),
+            ]
+        );
+    }
+}
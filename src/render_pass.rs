@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use crate::quad_pipeline::DrawQuadPipeline;
+use crate::screenshot::{copy_image_after, ImageReadback};
 use std::convert::TryFrom;
 use vulkano::{
     command_buffer::{
@@ -9,15 +10,16 @@ use vulkano::{
     },
     device::Queue,
     format::Format,
-    image::ImageAccess,
+    image::{view::ImageView, AttachmentImage, ImageAccess, ImageUsage, ImageViewAbstract},
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     sync::GpuFuture,
 };
-use vulkano_util::renderer::{DeviceImageView, SwapchainImageView};
+use vulkano_util::renderer::DeviceImageView;
 
 /// A render pass which places an image over screen frame
 pub struct FillScreenRenderPass {
     gfx_queue: Arc<Queue>,
+    output_format: Format,
     render_pass: Arc<RenderPass>,
     subpass: Subpass,
     quad_pipeline: DrawQuadPipeline,
@@ -45,19 +47,48 @@ impl FillScreenRenderPass {
 
         FillScreenRenderPass {
             gfx_queue,
+            output_format,
             render_pass,
             subpass,
             quad_pipeline,
         }
     }
 
-    /// Place view exactly over swapchain image target.
+    /// Render the frame `draw` would into an image of given size, and copy it to the CPU.
+    /// The readback matches the swapchain frame pixel for pixel when `size` is the swapchain's.
+    pub fn screenshot_after<F>(
+        &mut self,
+        before_future: F,
+        canvas_image: DeviceImageView,
+        size: [u32; 2],
+        clear_color: [f32; 4],
+    ) -> (Box<dyn GpuFuture>, ImageReadback)
+    where
+        F: GpuFuture + 'static,
+    {
+        let image = AttachmentImage::with_usage(
+            self.gfx_queue.device().clone(),
+            size,
+            self.output_format,
+            ImageUsage {
+                color_attachment: true,
+                transfer_src: true,
+                ..ImageUsage::none()
+            },
+        )
+        .unwrap();
+        let target = ImageView::new_default(image.clone()).unwrap();
+        let after_render = self.draw(before_future, canvas_image, target, clear_color);
+        copy_image_after(self.gfx_queue.clone(), image, after_render)
+    }
+
+    /// Place view exactly over target, e.g. a swapchain image.
     /// Texture draw pipeline uses a quad onto which it places the view.
     pub fn draw<F>(
         &mut self,
         before_future: F,
         canvas_image: DeviceImageView,
-        target: SwapchainImageView,
+        target: Arc<dyn ImageViewAbstract>,
         clear_color: [f32; 4],
     ) -> Box<dyn GpuFuture>
     where
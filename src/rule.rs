@@ -0,0 +1,557 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Largest neighbour count a Moore neighbourhood can have
+const MAX_NEIGHBOURS: u32 = 8;
+/// Largest number of cell states in Generations rules
+pub const MAX_STATES: u32 = 256;
+/// Largest neighbourhood radius of Larger than Life rules. Must match `MAX_RADIUS` in the life
+/// compute shader, which sizes its shared memory tile by it.
+pub const MAX_RADIUS: u32 = 16;
+
+/// Empty range of neighbour counts
+const NO_RANGE: (u32, u32) = (1, 0);
+
+/// Shape of the neighbourhood whose live cells are counted
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Neighbourhood {
+    /// Square of cells within radius
+    #[default]
+    Moore,
+    /// Diamond of cells within manhattan distance of radius
+    VonNeumann,
+}
+
+impl Neighbourhood {
+    /// Value of the neighbourhood in the compute shader
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            Neighbourhood::Moore => 0,
+            Neighbourhood::VonNeumann => 1,
+        }
+    }
+
+    /// Whether the offset from the center cell lies in the neighbourhood of given radius
+    pub fn contains(&self, dx: i32, dy: i32, radius: u32) -> bool {
+        let r = radius as i32;
+        match self {
+            Neighbourhood::Moore => dx.abs() <= r && dy.abs() <= r,
+            Neighbourhood::VonNeumann => dx.abs() + dy.abs() <= r,
+        }
+    }
+
+    /// Number of cells in the neighbourhood including the center
+    fn size(&self, radius: u32) -> u32 {
+        match self {
+            Neighbourhood::Moore => (2 * radius + 1) * (2 * radius + 1),
+            Neighbourhood::VonNeumann => 2 * radius * (radius + 1) + 1,
+        }
+    }
+}
+
+/// Outer totalistic Life-like rule in B/S notation, e.g. `B3/S23` for Conway's Game of Life.
+/// Birth and survival conditions are stored as bitmasks, where bit `n` is set if a cell with `n`
+/// live neighbours is born (or survives). These masks are passed as such to the compute shader.
+///
+/// Generations rules (`B2/S/C3`) have more than two states. State 0 is dead, 1 is alive and
+/// the rest are dying states. A live cell which doesn't survive starts dying, and dying cells
+/// advance a state each step until they're dead again. Only live cells count as neighbours.
+///
+/// Larger than Life rules (`R5,C0,M1,S34..58,B34..45,NM`) count neighbours within a larger
+/// radius, and birth and survival conditions are ranges of counts instead of masks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LifeRule {
+    birth: u32,
+    survival: u32,
+    birth_range: (u32, u32),
+    survival_range: (u32, u32),
+    states: u32,
+    radius: u32,
+    neighbourhood: Neighbourhood,
+    include_center: bool,
+}
+
+impl LifeRule {
+    /// Conway's Game of Life
+    pub const CONWAY: LifeRule = LifeRule {
+        birth: 1 << 3,
+        survival: 1 << 2 | 1 << 3,
+        birth_range: NO_RANGE,
+        survival_range: NO_RANGE,
+        states: 2,
+        radius: 1,
+        neighbourhood: Neighbourhood::Moore,
+        include_center: false,
+    };
+
+    /// Create a two state rule from neighbour counts causing birth and survival
+    pub fn new(birth: &[u32], survival: &[u32]) -> Result<LifeRule, LifeRuleError> {
+        Ok(LifeRule {
+            birth: counts_to_mask(birth)?,
+            survival: counts_to_mask(survival)?,
+            ..LifeRule::CONWAY
+        })
+    }
+
+    /// Create a two state Larger than Life rule. Birth and survival are inclusive ranges of
+    /// live cell counts within the neighbourhood.
+    pub fn larger_than_life(
+        radius: u32,
+        neighbourhood: Neighbourhood,
+        include_center: bool,
+        birth: (u32, u32),
+        survival: (u32, u32),
+    ) -> Result<LifeRule, LifeRuleError> {
+        if !(1..=MAX_RADIUS).contains(&radius) {
+            return Err(LifeRuleError::InvalidRadius(radius));
+        }
+        let max_count = neighbourhood.size(radius) - if include_center { 0 } else { 1 };
+        for &(min, max) in [birth, survival].iter() {
+            if min > max || max > max_count {
+                return Err(LifeRuleError::InvalidRange(min, max));
+            }
+        }
+        Ok(LifeRule {
+            birth: 0,
+            survival: 0,
+            birth_range: birth,
+            survival_range: survival,
+            states: 2,
+            radius,
+            neighbourhood,
+            include_center,
+        })
+    }
+
+    /// Turn the rule into a Generations rule with `states` cell states (including dead and alive)
+    pub fn with_states(self, states: u32) -> Result<LifeRule, LifeRuleError> {
+        if !(2..=MAX_STATES).contains(&states) {
+            return Err(LifeRuleError::InvalidStateCount(states));
+        }
+        Ok(LifeRule { states, ..self })
+    }
+
+    /// Number of cell states, 2 for Life-like rules
+    pub fn states(&self) -> u32 {
+        self.states
+    }
+
+    /// Bit `n` is set if a dead cell with `n` live neighbours becomes alive
+    pub fn birth_mask(&self) -> u32 {
+        self.birth
+    }
+
+    /// Bit `n` is set if a live cell with `n` live neighbours stays alive
+    pub fn survival_mask(&self) -> u32 {
+        self.survival
+    }
+
+    /// Inclusive range of live neighbour counts causing birth in Larger than Life rules.
+    /// Empty (min > max) for B/S rules.
+    pub fn birth_range(&self) -> (u32, u32) {
+        self.birth_range
+    }
+
+    /// Inclusive range of live neighbour counts causing survival in Larger than Life rules.
+    /// Empty (min > max) for B/S rules.
+    pub fn survival_range(&self) -> (u32, u32) {
+        self.survival_range
+    }
+
+    pub fn radius(&self) -> u32 {
+        self.radius
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    /// Whether the cell itself is counted in its neighbourhood
+    pub fn include_center(&self) -> bool {
+        self.include_center
+    }
+
+    pub fn is_born(&self, neighbours: u32) -> bool {
+        (neighbours <= MAX_NEIGHBOURS && self.birth & (1 << neighbours) != 0)
+            || (self.birth_range.0..=self.birth_range.1).contains(&neighbours)
+    }
+
+    pub fn survives(&self, neighbours: u32) -> bool {
+        (neighbours <= MAX_NEIGHBOURS && self.survival & (1 << neighbours) != 0)
+            || (self.survival_range.0..=self.survival_range.1).contains(&neighbours)
+    }
+
+    /// State of a cell in the next generation given its current state and live neighbour count.
+    /// Must match `compute_life` in the life compute shader.
+    pub fn next_state(&self, state: u32, neighbours: u32) -> u32 {
+        match state {
+            0 if self.is_born(neighbours) => 1,
+            0 => 0,
+            1 if self.survives(neighbours) => 1,
+            _ => (state + 1) % self.states,
+        }
+    }
+
+    /// Whether the rule needs Larger than Life notation, i.e. it has neighbour count ranges or a
+    /// neighbourhood other than the 8 cells around
+    pub fn is_larger_than_life(&self) -> bool {
+        self.radius != 1
+            || self.neighbourhood != Neighbourhood::Moore
+            || self.include_center
+            || self.birth_range != NO_RANGE
+            || self.survival_range != NO_RANGE
+    }
+}
+
+impl Default for LifeRule {
+    fn default() -> Self {
+        LifeRule::CONWAY
+    }
+}
+
+fn counts_to_mask(counts: &[u32]) -> Result<u32, LifeRuleError> {
+    counts.iter().try_fold(0, |mask, &count| {
+        if count > MAX_NEIGHBOURS {
+            Err(LifeRuleError::InvalidNeighbourCount(count))
+        } else {
+            Ok(mask | 1 << count)
+        }
+    })
+}
+
+fn parse_number(digits: &str) -> Result<u32, LifeRuleError> {
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_digit()) {
+        return Err(LifeRuleError::InvalidCharacter(c));
+    }
+    digits
+        .parse::<u32>()
+        .map_err(|_| LifeRuleError::Malformed(digits.to_string()))
+}
+
+fn parse_states(digits: &str) -> Result<u32, LifeRuleError> {
+    let states = parse_number(digits).map_err(|_| LifeRuleError::InvalidStateCount(0))?;
+    if !(2..=MAX_STATES).contains(&states) {
+        return Err(LifeRuleError::InvalidStateCount(states));
+    }
+    Ok(states)
+}
+
+fn parse_counts(digits: &str) -> Result<u32, LifeRuleError> {
+    digits.chars().try_fold(0, |mask, c| match c.to_digit(10) {
+        Some(count) if count <= MAX_NEIGHBOURS => Ok(mask | 1 << count),
+        Some(count) => Err(LifeRuleError::InvalidNeighbourCount(count)),
+        None => Err(LifeRuleError::InvalidCharacter(c)),
+    })
+}
+
+/// Parse `min..max`, or a single count
+fn parse_range(s: &str) -> Result<(u32, u32), LifeRuleError> {
+    match s.split_once("..") {
+        Some((min, max)) => Ok((parse_number(min)?, parse_number(max)?)),
+        None => parse_number(s).map(|count| (count, count)),
+    }
+}
+
+fn write_counts(f: &mut fmt::Formatter<'_>, mask: u32) -> fmt::Result {
+    for count in 0..=MAX_NEIGHBOURS {
+        if mask & (1 << count) != 0 {
+            write!(f, "{}", count)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse Larger than Life rules in the notation used by Golly, e.g. `R5,C0,M1,S34..58,B34..45,NM`.
+/// `C` (states), `M` (count middle) and `N` (neighbourhood) are optional.
+fn parse_larger_than_life(s: &str) -> Result<LifeRule, LifeRuleError> {
+    let mut radius = None;
+    let mut states = None;
+    let mut include_center = None;
+    let mut survival = None;
+    let mut birth = None;
+    let mut neighbourhood = None;
+    for part in s.split(',') {
+        let part = part.trim();
+        let mut chars = part.chars();
+        let key = chars.next().map(|c| c.to_ascii_uppercase());
+        let value = chars.as_str();
+        match key {
+            Some('R') if radius.is_none() => radius = Some(parse_number(value)?),
+            // C0 and C1 mean two states, as does C2
+            Some('C') if states.is_none() => states = Some(parse_number(value)?.max(2)),
+            Some('M') if include_center.is_none() => {
+                include_center = match value {
+                    "0" => Some(false),
+                    "1" => Some(true),
+                    _ => return Err(LifeRuleError::Malformed(part.to_string())),
+                }
+            }
+            Some('S') if survival.is_none() => survival = Some(parse_range(value)?),
+            Some('B') if birth.is_none() => birth = Some(parse_range(value)?),
+            Some('N') if neighbourhood.is_none() => {
+                neighbourhood = match value.to_ascii_uppercase().as_str() {
+                    "M" => Some(Neighbourhood::Moore),
+                    "N" => Some(Neighbourhood::VonNeumann),
+                    _ => return Err(LifeRuleError::Malformed(part.to_string())),
+                }
+            }
+            _ => return Err(LifeRuleError::Malformed(s.to_string())),
+        }
+    }
+    LifeRule::larger_than_life(
+        radius.ok_or_else(|| LifeRuleError::Malformed(s.to_string()))?,
+        neighbourhood.unwrap_or_default(),
+        include_center.unwrap_or(false),
+        birth.ok_or(LifeRuleError::MissingBirth)?,
+        survival.ok_or(LifeRuleError::MissingSurvival)?,
+    )?
+    .with_states(states.unwrap_or(2))
+}
+
+/// Parses rulestrings in B/S notation (`B36/S23`), in either order and case insensitive.
+/// The older S/B notation without prefixes (`23/36`) is accepted too.
+/// Generations rules have a third part with the number of states (`B2/S/C3` or `/2/3`).
+/// Larger than Life rules are recognized by their comma separated form (`R5,C0,M1,S34..58,B34..45,NM`).
+impl FromStr for LifeRule {
+    type Err = LifeRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(LifeRuleError::Empty);
+        }
+        if s.contains(',') {
+            return parse_larger_than_life(s);
+        }
+        let parts = s.split('/').collect::<Vec<&str>>();
+        if parts.len() != 2 && parts.len() != 3 {
+            return Err(LifeRuleError::Malformed(s.to_string()));
+        }
+        let mut birth = None;
+        let mut survival = None;
+        let mut states = None;
+        for (i, part) in parts.iter().enumerate() {
+            let part = part.trim();
+            let mut chars = part.chars();
+            match chars.next().map(|c| c.to_ascii_uppercase()) {
+                Some('B') if birth.is_none() => birth = Some(parse_counts(chars.as_str())?),
+                Some('S') if survival.is_none() => survival = Some(parse_counts(chars.as_str())?),
+                Some('C') if states.is_none() => states = Some(parse_states(chars.as_str())?),
+                Some('B') | Some('S') | Some('C') => {
+                    return Err(LifeRuleError::Malformed(s.to_string()))
+                }
+                // S/B(/C) notation, survival first
+                _ if i == 0 => survival = Some(parse_counts(part)?),
+                _ if i == 1 => birth = Some(parse_counts(part)?),
+                _ => states = Some(parse_states(part)?),
+            }
+        }
+        Ok(LifeRule {
+            birth: birth.ok_or(LifeRuleError::MissingBirth)?,
+            survival: survival.ok_or(LifeRuleError::MissingSurvival)?,
+            states: states.unwrap_or(2),
+            ..LifeRule::CONWAY
+        })
+    }
+}
+
+/// Formats the rule in canonical B/S notation, e.g. `B36/S23`, or `B2/S/C3` for Generations.
+/// Larger than Life rules are formatted in full, e.g. `R5,C0,M1,S34..58,B34..45,NM`
+impl fmt::Display for LifeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_larger_than_life() {
+            return write!(
+                f,
+                "R{},C{},M{},S{}..{},B{}..{},N{}",
+                self.radius,
+                if self.states > 2 { self.states } else { 0 },
+                self.include_center as u32,
+                self.survival_range.0,
+                self.survival_range.1,
+                self.birth_range.0,
+                self.birth_range.1,
+                match self.neighbourhood {
+                    Neighbourhood::Moore => "M",
+                    Neighbourhood::VonNeumann => "N",
+                }
+            );
+        }
+        write!(f, "B")?;
+        write_counts(f, self.birth)?;
+        write!(f, "/S")?;
+        write_counts(f, self.survival)?;
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LifeRuleError {
+    Empty,
+    Malformed(String),
+    MissingBirth,
+    MissingSurvival,
+    InvalidCharacter(char),
+    InvalidNeighbourCount(u32),
+    InvalidStateCount(u32),
+    InvalidRadius(u32),
+    InvalidRange(u32, u32),
+}
+
+impl fmt::Display for LifeRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifeRuleError::Empty => write!(f, "empty rulestring"),
+            LifeRuleError::Malformed(s) => {
+                write!(f, "malformed rulestring '{}', expected e.g. 'B3/S23'", s)
+            }
+            LifeRuleError::MissingBirth => write!(f, "rulestring has no birth (B) conditions"),
+            LifeRuleError::MissingSurvival => {
+                write!(f, "rulestring has no survival (S) conditions")
+            }
+            LifeRuleError::InvalidCharacter(c) => {
+                write!(f, "invalid character '{}' in rulestring", c)
+            }
+            LifeRuleError::InvalidNeighbourCount(n) => write!(
+                f,
+                "invalid neighbour count {}, must be at most {}",
+                n, MAX_NEIGHBOURS
+            ),
+            LifeRuleError::InvalidStateCount(n) => write!(
+                f,
+                "invalid number of states {}, must be between 2 and {}",
+                n, MAX_STATES
+            ),
+            LifeRuleError::InvalidRadius(r) => write!(
+                f,
+                "invalid neighbourhood radius {}, must be between 1 and {}",
+                r, MAX_RADIUS
+            ),
+            LifeRuleError::InvalidRange(min, max) => write!(
+                f,
+                "invalid neighbour count range {}..{} for the neighbourhood",
+                min, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LifeRuleError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> LifeRule {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn conway_round_trips() {
+        let rule = parse("B3/S23");
+        assert_eq!(rule, LifeRule::CONWAY);
+        assert_eq!(rule.to_string(), "B3/S23");
+        assert_eq!(rule.birth_mask(), 1 << 3);
+        assert_eq!(rule.survival_mask(), 1 << 2 | 1 << 3);
+        assert!(!rule.is_larger_than_life());
+    }
+
+    #[test]
+    fn parses_notation_variants() {
+        let highlife = LifeRule::new(&[3, 6], &[2, 3]).unwrap();
+        assert_eq!(parse("B36/S23"), highlife);
+        assert_eq!(parse("s23/b36"), highlife);
+        assert_eq!(parse(" B36 / S23 "), highlife);
+        // S/B notation
+        assert_eq!(parse("23/36"), highlife);
+        assert_eq!(parse("B0/S8").to_string(), "B0/S8");
+        assert_eq!(parse("B/S").to_string(), "B/S");
+    }
+
+    #[test]
+    fn generations_round_trip() {
+        let brians_brain = parse("B2/S/C3");
+        assert_eq!(brians_brain.states(), 3);
+        assert_eq!(brians_brain.to_string(), "B2/S/C3");
+        assert_eq!(parse("/2/3"), brians_brain);
+        assert_eq!(parse(&brians_brain.to_string()), brians_brain);
+        let star_wars = parse("B2/S345/C4");
+        assert_eq!(parse(&star_wars.to_string()), star_wars);
+        // Dying cells advance until dead, regardless of neighbours
+        assert_eq!(brians_brain.next_state(1, 2), 2);
+        assert_eq!(brians_brain.next_state(2, 2), 0);
+        assert_eq!(brians_brain.next_state(0, 2), 1);
+    }
+
+    #[test]
+    fn larger_than_life_round_trips() {
+        let bosco = parse("R5,C0,M1,S34..58,B34..45,NM");
+        assert_eq!(bosco.radius(), 5);
+        assert_eq!(bosco.states(), 2);
+        assert!(bosco.include_center());
+        assert_eq!(bosco.neighbourhood(), Neighbourhood::Moore);
+        assert_eq!(bosco.survival_range(), (34, 58));
+        assert_eq!(bosco.birth_range(), (34, 45));
+        assert!(bosco.is_larger_than_life());
+        assert_eq!(bosco.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+        assert!(bosco.is_born(40) && !bosco.is_born(46));
+        assert!(bosco.survives(58) && !bosco.survives(33));
+
+        let von_neumann = parse("R2,C3,M0,S1..3,B2,NN");
+        assert_eq!(von_neumann.neighbourhood(), Neighbourhood::VonNeumann);
+        assert_eq!(von_neumann.states(), 3);
+        assert_eq!(von_neumann.birth_range(), (2, 2));
+        assert_eq!(parse(&von_neumann.to_string()), von_neumann);
+
+        // Optional parts default to two states, no center & Moore
+        let minimal = parse("R1,S2..3,B3");
+        assert_eq!(minimal.to_string(), "R1,C0,M0,S2..3,B3..3,NM");
+        assert!(minimal.is_larger_than_life());
+        assert_eq!(parse(&minimal.to_string()), minimal);
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        assert_eq!(Neighbourhood::Moore.size(2), 25);
+        assert_eq!(Neighbourhood::VonNeumann.size(2), 13);
+        assert!(Neighbourhood::Moore.contains(2, -2, 2));
+        assert!(!Neighbourhood::VonNeumann.contains(2, -1, 2));
+        assert!(Neighbourhood::VonNeumann.contains(1, -1, 2));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |s: &str| s.parse::<LifeRule>().unwrap_err();
+        assert_eq!(err(""), LifeRuleError::Empty);
+        assert_eq!(err("B3"), LifeRuleError::Malformed("B3".to_string()));
+        assert_eq!(err("B3/B3"), LifeRuleError::Malformed("B3/B3".to_string()));
+        assert_eq!(err("B3/C3"), LifeRuleError::MissingSurvival);
+        assert_eq!(err("S23/C3"), LifeRuleError::MissingBirth);
+        assert_eq!(err("B3x/S23"), LifeRuleError::InvalidCharacter('x'));
+        assert_eq!(err("B9/S23"), LifeRuleError::InvalidNeighbourCount(9));
+        assert_eq!(err("B3/S23/C1"), LifeRuleError::InvalidStateCount(1));
+        assert_eq!(err("B3/S23/C257"), LifeRuleError::InvalidStateCount(257));
+        assert_eq!(err("R0,S1,B1"), LifeRuleError::InvalidRadius(0));
+        assert_eq!(err("R17,S1,B1"), LifeRuleError::InvalidRadius(17));
+        assert_eq!(err("R1,S5..4,B1"), LifeRuleError::InvalidRange(5, 4));
+        // Radius 1 Moore has 8 neighbours without the center
+        assert_eq!(err("R1,S1..9,B1"), LifeRuleError::InvalidRange(1, 9));
+        assert_eq!(err("R1,S1"), LifeRuleError::MissingBirth);
+        assert_eq!(err("R1,B1"), LifeRuleError::MissingSurvival);
+        assert_eq!(
+            err("R1,M2,S1,B1"),
+            LifeRuleError::Malformed("M2".to_string())
+        );
+        assert_eq!(
+            LifeRule::new(&[3], &[9]),
+            Err(LifeRuleError::InvalidNeighbourCount(9))
+        );
+        assert_eq!(
+            LifeRule::CONWAY.with_states(300),
+            Err(LifeRuleError::InvalidStateCount(300))
+        );
+        assert!(err("B3").to_string().contains("B3/S23"));
+    }
+}
@@ -0,0 +1,249 @@
+use std::convert::TryInto;
+use std::fmt;
+
+use crate::rule::LifeRuleError;
+
+/// First bytes of every save
+pub(crate) const SAVE_MAGIC: &[u8; 4] = b"LIFE";
+/// Version of the save format. Bump when the layout changes, and keep loading older versions.
+/// Version 2 adds the seed, random number position & ages.
+pub(crate) const SAVE_VERSION: u32 = 2;
+
+/// Writes a save: magic & version, then fields in little endian, then a CRC-32 of everything
+/// before it.
+pub(crate) struct SaveWriter {
+    bytes: Vec<u8>,
+}
+
+impl SaveWriter {
+    pub fn new() -> SaveWriter {
+        let mut bytes = SAVE_MAGIC.to_vec();
+        bytes.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        SaveWriter { bytes }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Length prefixed string
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let checksum = crc32(&self.bytes);
+        self.write_u32(checksum);
+        self.bytes
+    }
+}
+
+/// Reads a save written by `SaveWriter`. Header & checksum are checked before any field is read.
+pub(crate) struct SaveReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    version: u32,
+}
+
+impl<'a> SaveReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<SaveReader<'a>, SaveError> {
+        if bytes.len() < SAVE_MAGIC.len() || &bytes[..SAVE_MAGIC.len()] != SAVE_MAGIC {
+            return Err(SaveError::InvalidMagic);
+        }
+        if bytes.len() < SAVE_MAGIC.len() + 8 {
+            return Err(SaveError::Truncated);
+        }
+        let (contents, checksum) = bytes.split_at(bytes.len() - 4);
+        if crc32(contents) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err(SaveError::InvalidChecksum);
+        }
+        let mut reader = SaveReader {
+            bytes: contents,
+            offset: SAVE_MAGIC.len(),
+            version: 0,
+        };
+        reader.version = reader.read_u32()?;
+        if !(1..=SAVE_VERSION).contains(&reader.version) {
+            return Err(SaveError::UnsupportedVersion(reader.version));
+        }
+        Ok(reader)
+    }
+
+    /// Version the save was written with, fields added later are missing from older saves
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SaveError> {
+        if self.bytes.len() - self.offset < len {
+            return Err(SaveError::Truncated);
+        }
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, SaveError> {
+        Ok(f32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_str(&mut self) -> Result<&'a str, SaveError> {
+        let len = self.read_u32()? as usize;
+        std::str::from_utf8(self.read_bytes(len)?).map_err(|_| SaveError::InvalidValue("string"))
+    }
+
+    /// Whether all fields have been read
+    pub fn is_finished(&self) -> bool {
+        self.offset == self.bytes.len()
+    }
+}
+
+/// CRC-32 (IEEE) of bytes
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveError {
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    InvalidChecksum,
+    Truncated,
+    InvalidRule(LifeRuleError),
+    /// Name of the field with an invalid value
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::InvalidMagic => write!(f, "not a life save"),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported save version {}, expected {}",
+                version, SAVE_VERSION
+            ),
+            SaveError::InvalidChecksum => write!(f, "save is corrupted, checksum doesn't match"),
+            SaveError::Truncated => write!(f, "save is truncated"),
+            SaveError::InvalidRule(e) => write!(f, "invalid rule in save: {}", e),
+            SaveError::InvalidValue(field) => write!(f, "invalid {} in save", field),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save() -> Vec<u8> {
+        let mut writer = SaveWriter::new();
+        writer.write_u8(7);
+        writer.write_u32(0xDEAD_BEEF);
+        writer.write_u64(u64::MAX - 1);
+        writer.write_f32(0.25);
+        writer.write_str("B3/S23");
+        writer.finish()
+    }
+
+    #[test]
+    fn fields_round_trip() {
+        let bytes = save();
+        let mut reader = SaveReader::new(&bytes).unwrap();
+        assert_eq!(reader.version(), SAVE_VERSION);
+        assert_eq!(reader.read_u8(), Ok(7));
+        assert_eq!(reader.read_u32(), Ok(0xDEAD_BEEF));
+        assert_eq!(reader.read_u64(), Ok(u64::MAX - 1));
+        assert_eq!(reader.read_f32(), Ok(0.25));
+        assert!(!reader.is_finished());
+        assert_eq!(reader.read_str(), Ok("B3/S23"));
+        assert!(reader.is_finished());
+        assert_eq!(reader.read_u8(), Err(SaveError::Truncated));
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn corruption_is_detected() {
+        let bytes = save();
+        // Any flipped bit fails the checksum, including in the checksum itself
+        for i in SAVE_MAGIC.len()..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 0x10;
+            assert_eq!(
+                SaveReader::new(&corrupted).err(),
+                Some(SaveError::InvalidChecksum)
+            );
+        }
+        assert_eq!(
+            SaveReader::new(&bytes[..bytes.len() - 1]).err(),
+            Some(SaveError::InvalidChecksum)
+        );
+        assert_eq!(
+            SaveReader::new(&bytes[..SAVE_MAGIC.len() + 4]).err(),
+            Some(SaveError::Truncated)
+        );
+        assert_eq!(
+            SaveReader::new(b"PNG\0....").err(),
+            Some(SaveError::InvalidMagic)
+        );
+    }
+
+    #[test]
+    fn versions() {
+        // Checksum is valid, but the version isn't known
+        let mut bytes = SAVE_MAGIC.to_vec();
+        bytes.extend_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+        assert_eq!(
+            SaveReader::new(&bytes).err(),
+            Some(SaveError::UnsupportedVersion(SAVE_VERSION + 1))
+        );
+
+        // Older versions still load
+        let mut bytes = SAVE_MAGIC.to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+        let reader = SaveReader::new(&bytes).unwrap();
+        assert_eq!(reader.version(), 1);
+        assert!(reader.is_finished());
+    }
+}
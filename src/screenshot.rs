@@ -0,0 +1,194 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+    sync::Arc,
+};
+
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo},
+    device::Queue,
+    format::Format,
+    image::ImageAccess,
+    sync::{self, GpuFuture},
+};
+use vulkano_util::renderer::DeviceImageView;
+
+/// Pixels of an image copied to a CPU buffer by `copy_image_after`
+pub struct ImageReadback {
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    size: [u32; 2],
+    format: Format,
+    flip_rows: bool,
+}
+
+impl ImageReadback {
+    /// Image size in pixels
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Reverse row order of `rgba`. Color images of simulations are shown with row 0 at the
+    /// bottom, so flip them to get the image as it's seen on screen.
+    pub fn flip_rows(mut self) -> ImageReadback {
+        self.flip_rows = !self.flip_rows;
+        self
+    }
+
+    /// RGBA pixels row by row from the top. `None` if the GPU hasn't finished the copy yet.
+    pub fn rgba(&self) -> Option<Vec<u8>> {
+        let pixels = self.buffer.read().ok()?;
+        let bgra = is_bgra(self.format);
+        Some(rgba_rows(&pixels, self.size, self.flip_rows, bgra))
+    }
+
+    /// Save as PNG. `None` if the GPU hasn't finished the copy yet.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Option<Result<(), png::EncodingError>> {
+        let rgba = self.rgba()?;
+        Some(save_png(path, self.size, &rgba))
+    }
+}
+
+/// Pixels of an image, row by row from the top and flipped if `flip_rows`, as RGBA
+fn rgba_rows(pixels: &[u8], size: [u32; 2], flip_rows: bool, bgra: bool) -> Vec<u8> {
+    let row_len = size[0] as usize * 4;
+    let mut rgba = Vec::with_capacity(pixels.len());
+    for y in 0..size[1] as usize {
+        let y = if flip_rows {
+            size[1] as usize - 1 - y
+        } else {
+            y
+        };
+        rgba.extend_from_slice(&pixels[y * row_len..(y + 1) * row_len]);
+    }
+    if bgra {
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    rgba
+}
+
+fn is_bgra(format: Format) -> bool {
+    matches!(
+        format,
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB | Format::B8G8R8A8_SNORM
+    )
+}
+
+/// Copy an image to a CPU buffer after `before`, without waiting for the GPU. Image must have
+/// `transfer_src` usage and an 8 bit RGBA or BGRA format. Pixels can be read from the returned
+/// readback once the returned future has finished.
+pub fn copy_image_after<F>(
+    queue: Arc<Queue>,
+    image: Arc<dyn ImageAccess>,
+    before: F,
+) -> (Box<dyn GpuFuture>, ImageReadback)
+where
+    F: GpuFuture + 'static,
+{
+    let format = image.format();
+    assert!(
+        is_bgra(format)
+            || matches!(
+                format,
+                Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB | Format::R8G8B8A8_SNORM
+            ),
+        "Can't copy image of format {:?}, expected 8 bit RGBA or BGRA",
+        format
+    );
+    let size = image.dimensions().width_height();
+    let buffer = CpuAccessibleBuffer::from_iter(
+        queue.device().clone(),
+        BufferUsage::transfer_dst(),
+        true,
+        (0..size[0] * size[1] * 4).map(|_| 0u8),
+    )
+    .unwrap();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        queue.device().clone(),
+        queue.family(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    builder
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))
+        .unwrap();
+    let command_buffer = builder.build().unwrap();
+
+    let after = before.then_execute(queue, command_buffer).unwrap().boxed();
+    let readback = ImageReadback {
+        buffer,
+        size,
+        format,
+        flip_rows: false,
+    };
+    (after, readback)
+}
+
+/// Save a simulation color image, e.g. `GameOfLife::color_image`, as PNG the way it's shown on
+/// screen. Blocks until the GPU has finished.
+pub fn save_color_image_png<P: AsRef<Path>>(
+    queue: Arc<Queue>,
+    color_image: DeviceImageView,
+    path: P,
+) -> Result<(), png::EncodingError> {
+    let before = sync::now(queue.device().clone());
+    let (after, readback) = copy_image_after(queue, color_image.image().clone(), before);
+    // Future is dropped once finished, releasing the buffer for reading
+    let _ = after
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+    readback.flip_rows().save_png(path).unwrap_or_else(|| {
+        let e = io::Error::new(io::ErrorKind::Other, "image is still in use by the GPU");
+        Err(e.into())
+    })
+}
+
+/// Save RGBA pixels, row by row from the top, as PNG
+pub fn save_png<P: AsRef<Path>>(
+    path: P,
+    size: [u32; 2],
+    rgba: &[u8],
+) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), size[0], size[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x2 image, with a different color per pixel
+    const PIXELS: [u8; 16] = [
+        1, 2, 3, 4, 5, 6, 7, 8, //
+        9, 10, 11, 12, 13, 14, 15, 16,
+    ];
+
+    #[test]
+    fn rgba_rows_are_flipped() {
+        assert_eq!(rgba_rows(&PIXELS, [2, 2], false, false), PIXELS.to_vec());
+        assert_eq!(
+            rgba_rows(&PIXELS, [2, 2], true, false),
+            vec![9, 10, 11, 12, 13, 14, 15, 16, 1, 2, 3, 4, 5, 6, 7, 8]
+        );
+    }
+
+    #[test]
+    fn bgra_is_swizzled() {
+        assert!(is_bgra(Format::B8G8R8A8_SRGB));
+        assert!(!is_bgra(Format::R8G8B8A8_UNORM));
+        assert_eq!(
+            rgba_rows(&PIXELS, [2, 2], true, true),
+            vec![11, 10, 9, 12, 15, 14, 13, 16, 3, 2, 1, 4, 7, 6, 5, 8]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+use bevy::math::IVec2;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::boundary::BoundaryMode;
+use crate::brush::Brush;
+use crate::rule::LifeRule;
+
+/// Common interface of life simulation backends, so that the same setup can be run on the GPU
+/// (`GameOfLife`) or headless on the CPU (`CpuGameOfLife`) and their results compared.
+pub trait LifeSimulation {
+    /// Grid size in cells
+    fn size(&self) -> [u32; 2];
+
+    fn rule(&self) -> LifeRule;
+
+    /// Set the rule used for the following steps. Fails if the simulation can't run the rule.
+    fn set_rule(&mut self, rule: LifeRule) -> Result<(), UnsupportedRule>;
+
+    fn boundary_mode(&self) -> BoundaryMode;
+
+    fn set_boundary_mode(&mut self, boundary_mode: BoundaryMode);
+
+    /// Seed of the initial grid & brushes
+    fn seed(&self) -> u64;
+
+    /// Draw random life in a circle around pos
+    fn draw_life(&mut self, pos: IVec2, radius: i32) {
+        self.draw_brush(&Brush::spray(radius.max(0) as u32), pos, pos);
+    }
+
+    /// Draw with brush moving from `from` to `to`, see `Brush::cells`
+    fn draw_brush(&mut self, brush: &Brush, from: IVec2, to: IVec2);
+
+    /// Compute next step
+    fn compute(&mut self) {
+        self.compute_steps(1);
+    }
+
+    /// Compute next `steps` steps
+    fn compute_steps(&mut self, steps: u32);
+
+    /// Cell states of the current step, row by row. Mutable, as edits still queued are applied
+    /// first.
+    fn grid(&mut self) -> Vec<u32>;
+
+    /// Replace cell states of the current step, row by row
+    fn set_grid(&mut self, cells: &[u32]);
+}
+
+/// Rule a simulation can't run, e.g. because of how it stores cells
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedRule {
+    pub rule: LifeRule,
+    /// Why the rule isn't supported
+    pub reason: &'static str,
+}
+
+impl fmt::Display for UnsupportedRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported rule {}: {}", self.rule, self.reason)
+    }
+}
+
+impl std::error::Error for UnsupportedRule {}
+
+/// Random number generator of simulations. ChaCha gives the same numbers on every platform, so
+/// a run can be reproduced from its seed and inputs.
+pub type LifeRng = ChaCha8Rng;
+
+/// Fraction of cells alive in random grids, unless another density is given
+pub const DEFAULT_DENSITY: f32 = 0.5;
+
+/// Seed for simulations which aren't given one
+pub(crate) fn random_seed() -> u64 {
+    rand::thread_rng().gen()
+}
@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+
+/// Number of generations kept in `LifeStats` history
+const MAX_STATS_HISTORY: usize = 1024;
+
+/// Counts of a generation, reduced on the GPU. Only live cells (state 1) count as alive, dying
+/// states of Generations rules don't.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct GenerationStats {
+    /// Steps simulated since the simulation was created
+    pub generation: u32,
+    pub alive: u32,
+    /// Cells which were dead and are now alive
+    pub births: u32,
+    /// Cells which were alive and now aren't
+    pub deaths: u32,
+}
+
+/// Statistics of recent generations of `GameOfLife`, updated as the GPU finishes steps
+#[derive(Debug, Default)]
+pub struct LifeStats {
+    history: VecDeque<GenerationStats>,
+}
+
+impl LifeStats {
+    /// Stats of the latest finished generation
+    pub fn latest(&self) -> Option<GenerationStats> {
+        self.history.back().copied()
+    }
+
+    /// Stats of recent generations, oldest first
+    pub fn history(&self) -> &VecDeque<GenerationStats> {
+        &self.history
+    }
+
+    /// Whether all cells had died by the latest finished generation
+    pub fn is_extinct(&self) -> bool {
+        self.latest().map_or(false, |stats| stats.alive == 0)
+    }
+
+    pub fn push(&mut self, stats: GenerationStats) {
+        if self.history.len() == MAX_STATS_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(stats);
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(generation: u32, alive: u32) -> GenerationStats {
+        GenerationStats {
+            generation,
+            alive,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut life_stats = LifeStats::default();
+        for generation in 0..MAX_STATS_HISTORY as u32 + 10 {
+            life_stats.push(stats(generation, 1));
+        }
+        assert_eq!(life_stats.history().len(), MAX_STATS_HISTORY);
+        assert_eq!(life_stats.history().front().unwrap().generation, 10);
+        assert_eq!(
+            life_stats.latest().unwrap().generation,
+            MAX_STATS_HISTORY as u32 + 9
+        );
+        life_stats.clear();
+        assert_eq!(life_stats.latest(), None);
+    }
+
+    #[test]
+    fn extinct_after_latest_generation_dies() {
+        let mut life_stats = LifeStats::default();
+        assert!(!life_stats.is_extinct());
+        life_stats.push(stats(0, 0));
+        assert!(life_stats.is_extinct());
+        life_stats.push(stats(1, 3));
+        assert!(!life_stats.is_extinct());
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use bevy::input::mouse::MouseButton;
+use bevy::math::Vec2;
+
+/// Input a stroke is drawn with
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StrokeId {
+    Mouse(MouseButton),
+    /// Touch by its id, see `bevy::input::touch::Touch::id`
+    Touch(u64),
+}
+
+/// Stroke from where an input was pressed to its latest position
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stroke {
+    pub start: Vec2,
+    pub last: Vec2,
+}
+
+/// Strokes of inputs which are currently pressed. Positions are sampled once per frame, so fast
+/// strokes are drawn as segments between consecutive samples instead of at the samples only.
+#[derive(Debug, Default)]
+pub struct Strokes {
+    active: HashMap<StrokeId, Stroke>,
+}
+
+impl Strokes {
+    /// Continue the stroke of input to pos, or start it. Returns the segment moved since the
+    /// previous sample, which is just pos when the stroke starts.
+    pub fn update(&mut self, id: StrokeId, pos: Vec2) -> (Vec2, Vec2) {
+        let stroke = self.active.entry(id).or_insert(Stroke {
+            start: pos,
+            last: pos,
+        });
+        let from = stroke.last;
+        stroke.last = pos;
+        (from, pos)
+    }
+
+    /// End the stroke of input, if it was started
+    pub fn end(&mut self, id: StrokeId) -> Option<Stroke> {
+        self.active.remove(&id)
+    }
+
+    pub fn is_active(&self, id: StrokeId) -> bool {
+        self.active.contains_key(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+}
+
+/// Longest time in seconds from the first touch of a tap to its release
+pub const TAP_TIME: f64 = 0.3;
+/// Farthest distance in pixels a touch of a tap may move
+pub const TAP_DISTANCE: f32 = 20.0;
+
+/// Detects taps with one or more fingers: touches which barely move and are all released soon
+/// after the first one
+#[derive(Debug, Default)]
+pub struct TapGesture {
+    // Time the first touch started, while touches are down
+    started: Option<f64>,
+    // Most touches down at once
+    fingers: usize,
+    // Whether a touch moved too far or the touches were held too long
+    rejected: bool,
+}
+
+impl TapGesture {
+    /// Update with the start & current position of each pressed touch at time in seconds.
+    /// Returns the number of fingers once a tap's touches have all been released.
+    pub fn update(
+        &mut self,
+        time: f64,
+        touches: impl IntoIterator<Item = (Vec2, Vec2)>,
+    ) -> Option<usize> {
+        let mut pressed = 0;
+        for (start, pos) in touches {
+            pressed += 1;
+            self.rejected |= start.distance(pos) > TAP_DISTANCE;
+        }
+        if pressed > 0 {
+            let started = *self.started.get_or_insert(time);
+            self.fingers = self.fingers.max(pressed);
+            self.rejected |= time - started > TAP_TIME;
+            return None;
+        }
+        let started = self.started.take()?;
+        let fingers = std::mem::take(&mut self.fingers);
+        let rejected = std::mem::take(&mut self.rejected);
+        (!rejected && time - started <= TAP_TIME).then_some(fingers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touches(moved: f32) -> Vec<(Vec2, Vec2)> {
+        vec![
+            (Vec2::new(10.0, 10.0), Vec2::new(10.0 + moved, 10.0)),
+            (Vec2::new(50.0, 10.0), Vec2::new(50.0, 10.0)),
+        ]
+    }
+
+    #[test]
+    fn two_finger_tap() {
+        let mut tap = TapGesture::default();
+        assert_eq!(tap.update(0.0, touches(0.0)), None);
+        assert_eq!(tap.update(0.1, touches(5.0)), None);
+        assert_eq!(tap.update(0.2, vec![]), Some(2));
+        // Nothing is pressed after a tap
+        assert_eq!(tap.update(0.3, vec![]), None);
+    }
+
+    #[test]
+    fn tap_rejected_by_distance() {
+        let mut tap = TapGesture::default();
+        tap.update(0.0, touches(0.0));
+        tap.update(0.1, touches(TAP_DISTANCE + 1.0));
+        // Moving back doesn't make it a tap again
+        tap.update(0.15, touches(0.0));
+        assert_eq!(tap.update(0.2, vec![]), None);
+
+        // The next tap starts over
+        tap.update(1.0, touches(0.0));
+        assert_eq!(tap.update(1.1, vec![]), Some(2));
+    }
+
+    #[test]
+    fn tap_rejected_by_time() {
+        let mut tap = TapGesture::default();
+        tap.update(0.0, touches(0.0));
+        assert_eq!(tap.update(TAP_TIME + 0.1, vec![]), None);
+
+        tap.update(1.0, touches(0.0));
+        tap.update(1.0 + TAP_TIME + 0.1, touches(0.0));
+        assert_eq!(tap.update(1.0 + TAP_TIME + 0.2, vec![]), None);
+    }
+}
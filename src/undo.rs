@@ -0,0 +1,384 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::math::IVec2;
+
+use crate::edit::EditCommand;
+
+/// Undo steps kept, older ones are forgotten
+pub const UNDO_LIMIT: usize = 64;
+
+/// Undo or redo, e.g. sent by keyboard shortcuts & gestures
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HistoryCommand {
+    Undo,
+    Redo,
+}
+
+/// Change of one cell's state
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CellChange {
+    /// Index of the cell, row by row
+    pub index: u32,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Cells changed by edits, and only those. States fit in a byte, see `rule::MAX_STATES`.
+#[derive(Debug, Default, Clone)]
+pub struct EditDiff {
+    changes: Vec<CellChange>,
+    // Position of each cell's change in changes
+    positions: HashMap<u32, usize>,
+    // Changes of cells changed back. They're dropped once they're most of the changes, so that
+    // merging stays linear.
+    unchanged: usize,
+}
+
+impl EditDiff {
+    /// Diff of changes made in order, see `merge`
+    pub fn from_changes(changes: impl IntoIterator<Item = CellChange>) -> EditDiff {
+        let mut diff = EditDiff::default();
+        diff.merge(changes);
+        diff
+    }
+
+    /// Changed cells in order of their first change
+    pub fn changes(&self) -> impl Iterator<Item = &CellChange> + '_ {
+        self.changes
+            .iter()
+            .filter(|change| change.old != change.new)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.len() == self.unchanged
+    }
+
+    /// Add changes made after this diff. Cells changed more than once keep their first old and
+    /// last new state, and cells changed back are dropped.
+    pub fn merge(&mut self, later: impl IntoIterator<Item = CellChange>) {
+        for change in later {
+            match self.positions.get(&change.index) {
+                Some(&i) => {
+                    let merged = &mut self.changes[i];
+                    self.unchanged -= (merged.old == merged.new) as usize;
+                    merged.new = change.new;
+                    self.unchanged += (merged.old == merged.new) as usize;
+                }
+                None => {
+                    self.positions.insert(change.index, self.changes.len());
+                    self.unchanged += (change.old == change.new) as usize;
+                    self.changes.push(change);
+                }
+            }
+        }
+        if self.unchanged > self.changes.len() / 2 {
+            self.changes.retain(|change| change.old != change.new);
+            self.positions = self
+                .changes
+                .iter()
+                .enumerate()
+                .map(|(i, change)| (change.index, i))
+                .collect();
+            self.unchanged = 0;
+        }
+    }
+
+    /// Edit writing the old states of changed cells back
+    fn revert(&self, width: u32) -> EditCommand {
+        EditCommand::Cells(
+            self.changes()
+                .map(|change| (cell_pos(change.index, width), change.old as u32))
+                .collect(),
+        )
+    }
+
+    /// Edit writing the new states of changed cells again
+    fn reapply(&self, width: u32) -> EditCommand {
+        EditCommand::Cells(
+            self.changes()
+                .map(|change| (cell_pos(change.index, width), change.new as u32))
+                .collect(),
+        )
+    }
+}
+
+fn cell_pos(index: u32, width: u32) -> IVec2 {
+    IVec2::new((index % width) as i32, (index / width) as i32)
+}
+
+/// Undo & redo stacks of `GameOfLife` edits. Edits are grouped so that e.g. a whole stroke is
+/// undone at once. Their diffs are recorded by the edit pass on the GPU and arrive once it has
+/// finished, see `submitted` & `finished`.
+#[derive(Default)]
+pub(crate) struct EditHistory {
+    // Oldest first, with the group of their edits
+    undo: VecDeque<(u64, EditDiff)>,
+    redo: Vec<EditDiff>,
+    // Groups of the undoable edits of each submission whose diffs haven't arrived, oldest first
+    in_flight: VecDeque<Vec<u64>>,
+    last_group: u64,
+    // Group open with `begin_group` & how many times it has been opened
+    open_group: Option<(u64, u32)>,
+    // Group whose diffs are reverted instead of recorded, with its diffs which have arrived,
+    // see `discard_group`
+    discarded: Option<(u64, EditDiff)>,
+}
+
+impl EditHistory {
+    /// Group of a new edit
+    pub fn edit_group(&mut self) -> u64 {
+        match self.open_group {
+            Some((group, _)) => group,
+            None => self.new_group(),
+        }
+    }
+
+    fn new_group(&mut self) -> u64 {
+        self.last_group += 1;
+        self.last_group
+    }
+
+    /// Group following edits until `end_group`. Groups may be opened several times, e.g. once
+    /// per stroke, and close when each has ended.
+    pub fn begin_group(&mut self) {
+        self.open_group = match self.open_group {
+            Some((group, depth)) => Some((group, depth + 1)),
+            None => Some((self.new_group(), 1)),
+        };
+    }
+
+    pub fn end_group(&mut self) {
+        self.open_group = match self.open_group {
+            Some((group, depth)) if depth > 1 => Some((group, depth - 1)),
+            _ => None,
+        };
+    }
+
+    /// Close the open group to revert its edits instead of keeping them as an undo step.
+    /// Returns the group, so that its queued edits can be dropped. Its edits are reverted all at
+    /// once when the last of their diffs has arrived, see `take_revert`.
+    pub fn discard_group(&mut self) -> Option<u64> {
+        let (group, _) = self.open_group.take()?;
+        let diff = match self.undo.back() {
+            Some((top, _)) if *top == group => self.undo.pop_back().unwrap().1,
+            _ => EditDiff::default(),
+        };
+        self.discarded = Some((group, diff));
+        Some(group)
+    }
+
+    /// Undoable edits of the groups were submitted, in order. New edits can't be redone over, so
+    /// redo steps are forgotten.
+    pub fn submitted(&mut self, groups: Vec<u64>) {
+        self.redo.clear();
+        self.in_flight.push_back(groups);
+    }
+
+    /// Diffs of the oldest submission have arrived, see `EditReadback::diffs`
+    pub fn finished(&mut self, diffs: Vec<(u64, EditDiff)>) {
+        self.in_flight.pop_front();
+        for (group, diff) in diffs {
+            match &mut self.discarded {
+                Some((discarded, changes)) if *discarded == group => {
+                    changes.merge(diff.changes().copied())
+                }
+                _ => self.push(group, diff),
+            }
+        }
+    }
+
+    /// Whether diffs of submitted edits are yet to arrive. Undo steps wait for them, as they may
+    /// belong to the latest step.
+    pub fn is_waiting(&self) -> bool {
+        !self.in_flight.is_empty()
+    }
+
+    /// Edit reverting the discarded group once all its diffs have arrived
+    pub fn take_revert(&mut self, width: u32) -> Option<EditCommand> {
+        let (group, _) = self.discarded.as_ref()?;
+        if self.in_flight.iter().any(|groups| groups.contains(group)) {
+            return None;
+        }
+        let (_, diff) = self.discarded.take().unwrap();
+        (!diff.is_empty()).then(|| diff.revert(width))
+    }
+
+    fn push(&mut self, group: u64, diff: EditDiff) {
+        match self.undo.back_mut() {
+            Some((top, top_diff)) if *top == group => {
+                top_diff.merge(diff.changes().copied());
+                if top_diff.is_empty() {
+                    self.undo.pop_back();
+                }
+            }
+            _ if diff.is_empty() => {}
+            _ => {
+                self.undo.push_back((group, diff));
+                if self.undo.len() > UNDO_LIMIT {
+                    self.undo.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Edit undoing the latest undo step, if there's one
+    pub fn undo(&mut self, width: u32) -> Option<EditCommand> {
+        let (_, diff) = self.undo.pop_back()?;
+        let revert = diff.revert(width);
+        self.redo.push(diff);
+        Some(revert)
+    }
+
+    /// Edit redoing the latest undone step, if there's one
+    pub fn redo(&mut self, width: u32) -> Option<EditCommand> {
+        let diff = self.redo.pop()?;
+        let reapply = diff.reapply(width);
+        // Own group, so that following edits aren't merged into it
+        let group = self.new_group();
+        self.undo.push_back((group, diff));
+        Some(reapply)
+    }
+
+    /// Whether there's an undo step. Steps count once their diffs have arrived.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forget all steps, e.g. when cell indices change. Open groups stay open.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.in_flight.clear();
+        self.discarded = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 8;
+
+    fn change(index: u32, old: u8, new: u8) -> CellChange {
+        CellChange { index, old, new }
+    }
+
+    fn changes(diff: &EditDiff) -> Vec<CellChange> {
+        diff.changes().copied().collect()
+    }
+
+    fn cells(states: &[(u32, u32)]) -> EditCommand {
+        EditCommand::Cells(
+            states
+                .iter()
+                .map(|(index, state)| (cell_pos(*index, WIDTH), *state))
+                .collect(),
+        )
+    }
+
+    /// Submit an edit of the group changing cells, and receive its diff
+    fn record(history: &mut EditHistory, group: u64, diff: &[CellChange]) {
+        history.submitted(vec![group]);
+        history.finished(vec![(group, EditDiff::from_changes(diff.iter().copied()))]);
+    }
+
+    #[test]
+    fn merge_keeps_first_old_and_last_new() {
+        let mut diff = EditDiff::from_changes([change(3, 0, 1), change(5, 0, 1)]);
+        diff.merge([change(3, 1, 2), change(7, 2, 0)]);
+        assert_eq!(
+            changes(&diff),
+            vec![change(3, 0, 2), change(5, 0, 1), change(7, 2, 0)]
+        );
+    }
+
+    #[test]
+    fn merge_drops_reverted_cells() {
+        let mut diff = EditDiff::from_changes([change(3, 0, 1), change(5, 0, 1)]);
+        diff.merge([change(3, 1, 0)]);
+        assert_eq!(changes(&diff), vec![change(5, 0, 1)]);
+        diff.merge([change(5, 1, 0)]);
+        assert!(diff.is_empty());
+        // Changed again after being dropped
+        diff.merge([change(3, 0, 1)]);
+        assert_eq!(changes(&diff), vec![change(3, 0, 1)]);
+    }
+
+    #[test]
+    fn group_is_undone_at_once() {
+        let mut history = EditHistory::default();
+        history.begin_group();
+        let group = history.edit_group();
+        record(&mut history, group, &[change(1, 0, 1)]);
+        assert_eq!(history.edit_group(), group);
+        record(&mut history, group, &[change(1, 1, 0), change(2, 0, 1)]);
+        history.end_group();
+        let other = history.edit_group();
+        assert_ne!(other, group);
+        record(&mut history, other, &[change(4, 0, 1)]);
+
+        assert_eq!(history.undo(WIDTH), Some(cells(&[(4, 0)])));
+        assert_eq!(history.undo(WIDTH), Some(cells(&[(2, 0)])));
+        assert_eq!(history.undo(WIDTH), None);
+    }
+
+    #[test]
+    fn discarded_group_is_reverted_once_diffs_arrive() {
+        let mut history = EditHistory::default();
+        history.begin_group();
+        let group = history.edit_group();
+        history.submitted(vec![group]);
+        history.submitted(vec![group]);
+        assert!(history.is_waiting());
+        history.finished(vec![(group, EditDiff::from_changes([change(1, 0, 1)]))]);
+        assert!(history.can_undo());
+
+        assert_eq!(history.discard_group(), Some(group));
+        assert!(!history.can_undo());
+        // Second submission is still on its way
+        assert_eq!(history.take_revert(WIDTH), None);
+        history.finished(vec![(group, EditDiff::from_changes([change(2, 1, 0)]))]);
+        assert!(!history.is_waiting());
+        assert_eq!(history.take_revert(WIDTH), Some(cells(&[(1, 0), (2, 1)])));
+        assert_eq!(history.take_revert(WIDTH), None);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn oldest_steps_are_forgotten() {
+        let mut history = EditHistory::default();
+        for i in 0..=UNDO_LIMIT as u32 {
+            let group = history.edit_group();
+            record(&mut history, group, &[change(i, 0, 1)]);
+        }
+        let mut undone = vec![];
+        while let Some(revert) = history.undo(WIDTH) {
+            undone.push(revert);
+        }
+        assert_eq!(undone.len(), UNDO_LIMIT);
+        assert_eq!(undone[0], cells(&[(UNDO_LIMIT as u32, 0)]));
+        assert_eq!(undone[UNDO_LIMIT - 1], cells(&[(1, 0)]));
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let mut history = EditHistory::default();
+        let group = history.edit_group();
+        record(&mut history, group, &[change(1, 0, 1)]);
+        assert_eq!(history.undo(WIDTH), Some(cells(&[(1, 0)])));
+        assert!(history.can_redo());
+        assert_eq!(history.redo(WIDTH), Some(cells(&[(1, 1)])));
+        assert_eq!(history.undo(WIDTH), Some(cells(&[(1, 0)])));
+
+        // Queuing an edit keeps redo steps, submitting it drops them
+        let group = history.edit_group();
+        assert!(history.can_redo());
+        history.submitted(vec![group]);
+        assert!(!history.can_redo());
+        assert_eq!(history.redo(WIDTH), None);
+    }
+}